@@ -0,0 +1,159 @@
+/*
+ What enemies do about the player. Chasers and shooters keep an eye out for
+ them; everything else just patrols.
+
+   Patrol --sees the player--> Alert --after `reaction` s--> Chase
+   Chase --in reach--> Attack --out of reach--> Chase
+   Chase/Attack --player unseen for `memory` s--> Return --home--> Patrol
+
+ Seeing the player takes them being within the archetype's `sight` with
+ nothing solid in between (one-way platforms can be seen through). Chasers
+ run at them and charge once they're close; shooters stand their ground
+ and fire while the player is in range. Returning goes back to where the
+ patrol was left off.
+
+ Whatever it's doing, an enemy that walks never steps off a ledge or into a
+ wall (see Enemy::update), so patrols don't need bounds.
+*/
+
+use macroquad::math::Vec2;
+
+use crate::archetype::Behaviour;
+use crate::entities::Enemy;
+use crate::physics::{self, Solids};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AiState {
+    #[default]
+    Patrol,
+    /// Has just seen the player and is about to react.
+    Alert,
+    Chase,
+    Attack,
+    /// Lost the player and is heading back to its patrol.
+    Return,
+}
+
+/// How close a chaser gets before it charges.
+const CHARGE_RANGE: f32 = 80.0;
+/// How much faster than its speed a chaser charges.
+const CHARGE_SPEED: f32 = 1.6;
+/// How close (in x) counts as there, for getting to the player or home.
+const CLOSE_ENOUGH: f32 = 2.0;
+
+impl Enemy {
+    /// Whether it ever takes notice of the player.
+    pub fn engages(&self) -> bool {
+        self.archetype.behaviour == Behaviour::Chase || self.archetype.shooter.is_some()
+    }
+
+    /// Whether it can see `target` from where it is.
+    pub fn can_see(&self, target: Vec2, solids: &Solids) -> bool {
+        let eye = self.rect().center();
+        eye.distance(target) <= self.archetype.sight && physics::line_of_sight(eye, target, solids)
+    }
+
+    /// Whether `target` is close enough to attack: in range for a shooter,
+    /// or to charge at for a chaser.
+    fn in_reach(&self, target: Vec2) -> bool {
+        let distance = self.rect().center().distance(target);
+        match &self.archetype.shooter {
+            Some(shooter) => distance <= shooter.range,
+            None => distance <= CHARGE_RANGE,
+        }
+    }
+
+    /// Moves on to `state`.
+    pub fn enter(&mut self, state: AiState) {
+        self.state = state;
+        self.state_time = 0.0;
+    }
+
+    /// Moves the state machine on by `dt` and returns how fast to move
+    /// (negative for left), facing that way or at the player. `target` is
+    /// where the player is (their centre), if they're around to be seen.
+    pub fn think(&mut self, dt: f32, solids: &Solids, target: Option<Vec2>) -> f32 {
+        let seen = target.filter(|&target| self.engages() && self.can_see(target, solids));
+        self.state_time += dt;
+        match seen {
+            Some(target) => {
+                self.last_seen = target;
+                self.unseen = 0.0;
+            }
+            None => self.unseen += dt,
+        }
+        let in_reach = seen.is_some_and(|target| self.in_reach(target));
+        let home = self.home - self.pos.x;
+        let next = match self.state {
+            AiState::Patrol if seen.is_some() => {
+                self.home = self.pos.x;
+                AiState::Alert
+            }
+            AiState::Return if seen.is_some() => AiState::Alert,
+            AiState::Return if home.abs() <= CLOSE_ENOUGH => AiState::Patrol,
+            AiState::Alert if seen.is_none() => AiState::Return,
+            AiState::Alert if self.state_time >= self.archetype.reaction => AiState::Chase,
+            AiState::Chase | AiState::Attack if self.unseen >= self.archetype.memory => {
+                AiState::Return
+            }
+            AiState::Chase if in_reach => AiState::Attack,
+            AiState::Attack if !in_reach => AiState::Chase,
+            state => state,
+        };
+        if next != self.state {
+            self.enter(next);
+        }
+
+        let chases = self.archetype.behaviour == Behaviour::Chase;
+        let toward = self.last_seen.x - self.rect().center().x;
+        let dir = match self.state {
+            AiState::Patrol if self.archetype.behaviour == Behaviour::Stand => 0.0,
+            AiState::Patrol => {
+                if self.facing_right {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            AiState::Alert => {
+                self.face(toward);
+                0.0
+            }
+            AiState::Chase | AiState::Attack => {
+                self.face(toward);
+                if !chases || toward.abs() <= CLOSE_ENOUGH {
+                    0.0
+                } else if self.state == AiState::Attack {
+                    toward.signum() * CHARGE_SPEED
+                } else {
+                    toward.signum()
+                }
+            }
+            AiState::Return if home.abs() <= CLOSE_ENOUGH => 0.0,
+            AiState::Return => {
+                self.face(home);
+                home.signum()
+            }
+        };
+        dir * self.archetype.speed
+    }
+
+    /// Turns to face the way `dx` points, if it points anywhere.
+    fn face(&mut self, dx: f32) {
+        if dx != 0.0 {
+            self.facing_right = dx > 0.0;
+        }
+    }
+
+    /// Can't go any further right (or left): a patrol turns back, and so
+    /// does a return, giving up on getting home; anything else waits there.
+    pub fn blocked(&mut self, going_right: bool) {
+        self.vel.x = 0.0;
+        if matches!(self.state, AiState::Patrol | AiState::Return) {
+            if self.state == AiState::Return {
+                self.enter(AiState::Patrol);
+            }
+            self.facing_right = !going_right;
+        }
+    }
+}
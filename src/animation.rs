@@ -0,0 +1,276 @@
+/*
+ Sprite animation. Each kind of character has an AnimationSet in
+ assets/sprites.json, under "animations":
+
+ "animations": {
+     "player": {
+         "states": {
+             "idle": { "frames": ["player_idle_0", "player_idle_1"], "fps": 4 },
+             "walk": { "frames": ["player_walk_0", "player_walk_1"], "fps": 10,
+                       "events": { "1": "step" } },
+             "jump": { "frames": ["player_jump"], "loop": false }
+         },
+         "transitions": [ { "from": "fall", "event": "dust" } ]
+     }
+ }
+
+ An Animator follows one character: every frame the game works out which
+ AnimState the character is in (from its simulation state) and the
+ Animator plays that animation, firing events as frames come up and when
+ the state changes. States without an animation fall back to a similar
+ one (run to walk, fall to jump, ... and everything to idle).
+*/
+
+use std::collections::HashMap;
+
+use macroquad::math::{Vec2, vec2};
+use serde::Deserialize;
+
+use crate::archetype::Behaviour;
+use crate::entities::{Enemy, Player};
+use crate::sprites::Sprites;
+use crate::world::World;
+use crate::{PLAYER_HEIGHT, PLAYER_WIDTH};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimState {
+    Idle,
+    Walk,
+    Run,
+    Jump,
+    Fall,
+    Shoot,
+    Hurt,
+    Dead,
+}
+
+impl AnimState {
+    /// What to play instead when there's no animation for this state.
+    fn fallback(self) -> Option<AnimState> {
+        match self {
+            AnimState::Idle => None,
+            AnimState::Run => Some(AnimState::Walk),
+            AnimState::Fall => Some(AnimState::Jump),
+            AnimState::Dead => Some(AnimState::Hurt),
+            _ => Some(AnimState::Idle),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Animation {
+    /// Frame names in the atlas.
+    pub frames: Vec<String>,
+    #[serde(default = "default_fps")]
+    pub fps: f32,
+    /// Whether to start over after the last frame, or stay on it.
+    #[serde(default = "default_true", rename = "loop")]
+    pub looping: bool,
+    /// Events fired when a frame comes up, by frame index.
+    #[serde(default)]
+    pub events: HashMap<usize, String>,
+}
+
+fn default_fps() -> f32 {
+    8.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Animation {
+    fn frame_at(&self, time: f32) -> usize {
+        let frame = (time * self.fps) as usize;
+        if self.looping {
+            frame % self.frames.len()
+        } else {
+            frame.min(self.frames.len() - 1)
+        }
+    }
+}
+
+/// An event fired when a character goes from one state to another. A
+/// missing `from` or `to` matches any state.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    #[serde(default)]
+    pub from: Option<AnimState>,
+    #[serde(default)]
+    pub to: Option<AnimState>,
+    pub event: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationSet {
+    pub states: HashMap<AnimState, Animation>,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+}
+
+impl AnimationSet {
+    /// The animation played for `state`, after falling back.
+    fn animation(&self, state: AnimState) -> Option<&Animation> {
+        let mut state = Some(state);
+        while let Some(s) = state {
+            if let Some(animation) = self.states.get(&s) {
+                return Some(animation);
+            }
+            state = s.fallback();
+        }
+        None
+    }
+}
+
+/// Something an animation asked for, e.g. "dust", at a character's feet.
+pub struct AnimEvent {
+    pub name: String,
+    pub pos: Vec2,
+}
+
+#[derive(Default)]
+pub struct Animator {
+    state: Option<AnimState>,
+    time: f32,
+    frame: Option<usize>,
+}
+
+impl Animator {
+    /// Advances the animation by `dt` seconds with the character now in
+    /// `state` and its feet at `feet`, pushing any events fired.
+    pub fn update(
+        &mut self,
+        set: &AnimationSet,
+        state: AnimState,
+        feet: Vec2,
+        dt: f32,
+        events: &mut Vec<AnimEvent>,
+    ) {
+        let mut fire = |name: &str| {
+            events.push(AnimEvent {
+                name: name.to_string(),
+                pos: feet,
+            })
+        };
+        if self.state != Some(state) {
+            for t in &set.transitions {
+                let from = t.from.is_none_or(|from| self.state == Some(from));
+                let to = t.to.is_none_or(|to| to == state);
+                if from && to && self.state.is_some() {
+                    fire(&t.event);
+                }
+            }
+            self.state = Some(state);
+            self.time = 0.0;
+            self.frame = None;
+        } else {
+            self.time += dt;
+        }
+
+        let Some(animation) = set.animation(state) else {
+            return;
+        };
+        let frame = animation.frame_at(self.time);
+        if self.frame != Some(frame) {
+            if let Some(event) = animation.events.get(&frame) {
+                fire(event);
+            }
+            self.frame = Some(frame);
+        }
+    }
+
+    /// The atlas frame to draw now, if `set` animates the current state.
+    pub fn frame<'a>(&self, set: &'a AnimationSet) -> Option<&'a str> {
+        let animation = set.animation(self.state?)?;
+        let frame = animation.frame_at(self.time);
+        Some(&animation.frames[frame])
+    }
+}
+
+impl Player {
+    pub fn anim_state(&self) -> AnimState {
+        if !self.alive {
+            AnimState::Dead
+        } else if self.hurt_timer > 0.0 {
+            AnimState::Hurt
+        } else if self.shoot_timer > 0.0 {
+            AnimState::Shoot
+        } else if !self.on_ground {
+            if self.vel.y < 0.0 {
+                AnimState::Jump
+            } else {
+                AnimState::Fall
+            }
+        } else if self.vel.x != 0.0 {
+            if self.is_running {
+                AnimState::Run
+            } else {
+                AnimState::Walk
+            }
+        } else {
+            AnimState::Idle
+        }
+    }
+}
+
+impl Enemy {
+    pub fn anim_state(&self) -> AnimState {
+        if !self.alive {
+            AnimState::Dead
+        } else if self.vel.y > 0.0 && self.archetype.behaviour != Behaviour::Fly {
+            AnimState::Fall
+        } else if self.vel.x != 0.0 {
+            AnimState::Walk
+        } else {
+            AnimState::Idle
+        }
+    }
+
+    /// Which animation set (and static sprite) it's drawn with.
+    pub fn sprite_name(&self) -> &str {
+        &self.archetype.sprite
+    }
+}
+
+/// Animators for everyone in a World. Enemies are matched up by index, so
+/// `reset_enemies` has to be called whenever a level is (re)loaded.
+#[derive(Default)]
+pub struct Animators {
+    pub player: Animator,
+    pub enemies: Vec<Animator>,
+}
+
+impl Animators {
+    pub fn reset_enemies(&mut self) {
+        self.enemies.clear();
+    }
+
+    pub fn update(
+        &mut self,
+        world: &World,
+        sprites: &Sprites,
+        dt: f32,
+        events: &mut Vec<AnimEvent>,
+    ) {
+        let player = &world.player;
+        if let Some(set) = sprites.animations("player") {
+            let feet = player.pos + vec2(PLAYER_WIDTH / 2.0, PLAYER_HEIGHT);
+            self.player
+                .update(set, player.anim_state(), feet, dt, events);
+        }
+
+        self.enemies
+            .resize_with(world.enemies.len(), Animator::default);
+        for (enemy, animator) in world.enemies.iter().zip(&mut self.enemies) {
+            if let Some(set) = sprites.animations(enemy.sprite_name()) {
+                let rect = enemy.rect();
+                let feet = vec2(rect.center().x, rect.bottom());
+                animator.update(set, enemy.anim_state(), feet, dt, events);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+/*
+ Enemy archetypes: the kinds of enemy there are, read from enemies.json. A
+ level's enemies say which one they are with "kind", and everything else
+ about them (how they move, how big and tough they are, what kills them,
+ what they're worth and how they're drawn) comes from their archetype:
+
+ {
+     "walker": { "behaviour": "walk", "speed": 60, "vulnerable_to": "both" },
+     "turret": { "behaviour": "stand", "vulnerable_to": "bullets",
+                 "shooter": { "range": 400, "interval": 2 } }
+ }
+
+ Every field is optional; see Archetype::default() for the defaults. The
+ behaviours themselves are in Enemy::update, and what enemies do about the
+ player in ai.rs.
+*/
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use macroquad::math::{Vec2, vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::GRAVITY;
+use crate::data::{self, DataError};
+
+pub const ENEMIES_FILE: &str = "enemies.json";
+
+/// How an enemy gets about.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Behaviour {
+    /// Patrols between its bounds, turning at walls.
+    Walk,
+    /// Patrols like a walker, hopping all the way.
+    Jump,
+    /// Patrols without falling, bobbing up and down.
+    Fly,
+    /// Patrols, but runs at the player once it's seen them.
+    Chase,
+    /// Stays where it is.
+    Stand,
+}
+
+/// What can kill an enemy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Vulnerability {
+    Stomp,
+    Bullets,
+    Both,
+    Neither,
+}
+
+impl Vulnerability {
+    pub fn to_stomp(self) -> bool {
+        matches!(self, Vulnerability::Stomp | Vulnerability::Both)
+    }
+
+    pub fn to_bullets(self) -> bool {
+        matches!(self, Vulnerability::Bullets | Vulnerability::Both)
+    }
+}
+
+/// Makes an enemy shoot at the player whenever they're close enough.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Shooter {
+    /// How close the player's centre has to be to the enemy's.
+    pub range: f32,
+    /// Seconds the player has to stay in range for each shot.
+    pub interval: f32,
+    /// Bullet speed in pixels per second.
+    pub speed: f32,
+}
+
+impl Default for Shooter {
+    fn default() -> Self {
+        Shooter {
+            range: 350.0,
+            interval: 1.5,
+            speed: 250.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Archetype {
+    pub behaviour: Behaviour,
+    /// Walking (or flying) speed in pixels per second.
+    pub speed: f32,
+    /// The hitbox, `[w, h]`; it's also the size it's drawn at.
+    pub size: Vec2,
+    /// How much damage it takes to kill.
+    pub health: i32,
+    pub vulnerable_to: Vulnerability,
+    /// Points for killing it. Stomping on it is worth a little more.
+    pub score: i32,
+    pub gravity: f32,
+    /// For jumpers, how hard they hop.
+    pub jump_speed: f32,
+    /// How near the player has to be to be noticed, for chasers and
+    /// shooters; 0 for never.
+    pub sight: f32,
+    /// Seconds it takes to react to seeing the player.
+    pub reaction: f32,
+    /// Seconds it keeps after the player once it's lost sight of them.
+    pub memory: f32,
+    pub shooter: Option<Shooter>,
+    /// The sprite (and animation set) it's drawn with.
+    pub sprite: String,
+}
+
+impl Default for Archetype {
+    fn default() -> Self {
+        Archetype {
+            behaviour: Behaviour::Walk,
+            speed: 60.0,
+            size: vec2(28.0, 45.0),
+            health: 1,
+            vulnerable_to: Vulnerability::Both,
+            score: 100,
+            gravity: GRAVITY,
+            jump_speed: 350.0,
+            sight: 250.0,
+            reaction: 0.5,
+            memory: 2.0,
+            shooter: None,
+            sprite: "enemy".to_string(),
+        }
+    }
+}
+
+/// Every archetype, by name.
+#[derive(Clone, Default)]
+pub struct Archetypes {
+    by_name: BTreeMap<String, Archetype>,
+}
+
+impl Archetypes {
+    pub fn load(file: &Path) -> Result<Self, DataError> {
+        let by_name =
+            data::load_json_checked(file, |by_name: &mut BTreeMap<String, Archetype>| {
+                if by_name.is_empty() {
+                    return Err(DataError::new(file, "no enemy archetypes defined"));
+                }
+                for (name, archetype) in by_name.iter() {
+                    validate(file, name, archetype)?;
+                }
+                Ok(())
+            })?;
+        Ok(Archetypes { by_name })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Archetype> {
+        self.by_name.get(name)
+    }
+
+    /// The names of all the archetypes, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.by_name.keys().map(String::as_str)
+    }
+
+    /// "armoured, chaser, ..." for error messages.
+    pub fn list(&self) -> String {
+        self.names().collect::<Vec<_>>().join(", ")
+    }
+}
+
+fn validate(file: &Path, name: &str, archetype: &Archetype) -> Result<(), DataError> {
+    let field = |field: &str| format!("{}.{}", name, field);
+    let mut positive = vec![
+        ("size", archetype.size.x),
+        ("size", archetype.size.y),
+        ("jump_speed", archetype.jump_speed),
+    ];
+    if let Some(shooter) = &archetype.shooter {
+        positive.extend([
+            ("shooter.range", shooter.range),
+            ("shooter.interval", shooter.interval),
+            ("shooter.speed", shooter.speed),
+        ]);
+    }
+    for (name, value) in positive {
+        if value.is_nan() || value <= 0.0 {
+            return Err(DataError::field(file, field(name), "must be positive"));
+        }
+    }
+    for (name, value) in [
+        ("speed", archetype.speed),
+        ("sight", archetype.sight),
+        ("reaction", archetype.reaction),
+        ("memory", archetype.memory),
+    ] {
+        if value.is_nan() || value < 0.0 {
+            return Err(DataError::field(file, field(name), "can't be negative"));
+        }
+    }
+    if !archetype.gravity.is_finite() {
+        return Err(DataError::field(file, field("gravity"), "must be a number"));
+    }
+    if archetype.health < 1 {
+        return Err(DataError::field(
+            file,
+            field("health"),
+            "must be at least 1",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+/*
+ The camera: which part of the level is on screen. It follows a point (the
+ player, a little ahead of where they're facing) in both directions, but
+ only once that point leaves a dead zone in the middle of the screen, and
+ eases towards it rather than jumping. The view never goes past the level's
+ edges, except for some open sky above it.
+*/
+
+use macroquad::math::{Rect, Vec2, vec2};
+use serde::{Deserialize, Serialize};
+
+/// How the camera behaves; levels can change it with a "camera" entry.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// Half the width and height of the box in the middle of the screen
+    /// the followed point can move around in without the camera moving.
+    pub dead_zone: Vec2,
+    /// Roughly how many seconds the camera takes to catch up; 0 for none.
+    pub smoothing: f32,
+    /// How far ahead of the player to look, in the way they're facing.
+    pub look_ahead: f32,
+    /// How far above the highest platform the view can go.
+    pub headroom: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            dead_zone: vec2(40.0, 60.0),
+            smoothing: 0.15,
+            look_ahead: 80.0,
+            headroom: 250.0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Camera {
+    /// Top left corner of the view, in level coordinates.
+    pub pos: Vec2,
+    /// The point the view is centred on as it eases along, or `None` to
+    /// jump straight to the target on the next update.
+    center: Option<Vec2>,
+    /// Where the camera is heading: the followed point, held back by the
+    /// dead zone.
+    focus: Vec2,
+}
+
+impl Camera {
+    /// Makes the next update cut straight to its target, for when the
+    /// player has been put somewhere new.
+    pub fn reset(&mut self) {
+        self.center = None;
+    }
+
+    /// Moves the camera on by `dt` seconds, following `target` (looking
+    /// ahead to the right if `facing_right`) with a `view`-sized window
+    /// kept inside `bounds`.
+    pub fn update(
+        &mut self,
+        config: &CameraConfig,
+        target: Vec2,
+        facing_right: bool,
+        view: Vec2,
+        bounds: Rect,
+        dt: f32,
+    ) {
+        let dir = if facing_right { 1.0 } else { -1.0 };
+        let goal = target + vec2(dir * config.look_ahead, 0.0);
+
+        let center = match self.center {
+            None => {
+                self.focus = goal;
+                goal
+            }
+            Some(center) => {
+                let dz = config.dead_zone;
+                self.focus = self.focus.clamp(goal - dz, goal + dz);
+                let t = if config.smoothing > 0.0 {
+                    1.0 - (-dt / config.smoothing).exp()
+                } else {
+                    1.0
+                };
+                center.lerp(self.focus, t)
+            }
+        };
+        let center = clamp_center(center, view, bounds, config.headroom);
+        self.center = Some(center);
+        self.pos = center - view / 2.0;
+    }
+}
+
+/// Keeps the view centred on `center` inside `bounds` (plus `headroom`
+/// above). A level smaller than the view is lined up with its left and
+/// bottom edges.
+fn clamp_center(center: Vec2, view: Vec2, bounds: Rect, headroom: f32) -> Vec2 {
+    let half = view / 2.0;
+    let min_x = bounds.left() + half.x;
+    let max_x = (bounds.right() - half.x).max(min_x);
+    let max_y = bounds.bottom() - half.y;
+    let min_y = (bounds.top() - headroom + half.y).min(max_y);
+    vec2(center.x.clamp(min_x, max_x), center.y.clamp(min_y, max_y))
+}
@@ -0,0 +1,393 @@
+/*
+ Loading the game's JSON data files (levels, controls, ...) with errors that
+ point at the file, line and field at fault.
+*/
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Why a data file couldn't be loaded. `field` is the JSON path of the
+/// offending value (e.g. `enemies[1].right_bound`).
+#[derive(Debug)]
+pub struct DataError {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl DataError {
+    pub fn new(file: &Path, message: impl Into<String>) -> Self {
+        DataError {
+            file: file.to_path_buf(),
+            line: None,
+            column: None,
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn field(file: &Path, field: impl Into<String>, message: impl Into<String>) -> Self {
+        DataError {
+            field: Some(field.into()),
+            ..DataError::new(file, message)
+        }
+    }
+
+    /// Points an error found after parsing at the line and column of its
+    /// field in `text`, the JSON it was parsed from. Left as it is if the
+    /// field isn't in there (it was left out and defaulted).
+    pub fn locate(mut self, text: &str) -> Self {
+        if self.line.is_none()
+            && let Some(field) = &self.field
+            && let Some(offset) = find_field(text, field)
+        {
+            let before = &text[..offset];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            self.line = Some(before.matches('\n').count() + 1);
+            self.column = Some(before[line_start..].chars().count() + 1);
+        }
+        self
+    }
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        if let Some(field) = &self.field {
+            write!(f, ": {}", field)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for DataError {}
+
+/// `name` in the game's directory under the user's data directory (e.g.
+/// ~/.local/share/adventure/ on Linux), if the platform has one.
+pub fn user_data_file(name: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("adventure").join(name))
+}
+
+/// Writes `value` to `file` as pretty JSON, creating its directory first.
+pub fn save_json<T: Serialize>(file: &Path, value: &T) -> Result<(), DataError> {
+    let json =
+        serde_json::to_string_pretty(value).map_err(|e| DataError::new(file, e.to_string()))?;
+    write(file, &json)
+}
+
+/// Like `save_json`, but laid out the way the hand-written files are: a
+/// line for each field and list item, with short lists and objects (a
+/// position, an enemy) kept on one line.
+pub fn save_json_tidy<T: Serialize>(file: &Path, value: &T) -> Result<(), DataError> {
+    let value = serde_json::to_value(value).map_err(|e| DataError::new(file, e.to_string()))?;
+    let mut json = String::new();
+    write_tidy(&value, 0, &mut json);
+    json.push('\n');
+    write(file, &json)
+}
+
+/// Writes `text` to a temporary file next to `file` and renames it over
+/// `file`, so a crash halfway through leaves the old file rather than half
+/// of the new one.
+fn write(file: &Path, text: &str) -> Result<(), DataError> {
+    let io_error = |e: std::io::Error| DataError::new(file, e.to_string());
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let mut temp = file.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    fs::write(&temp, text)
+        .and_then(|()| fs::rename(&temp, file))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            io_error(e)
+        })
+}
+
+fn write_tidy(value: &Value, indent: usize, out: &mut String) {
+    let pad = |level: usize| "    ".repeat(level);
+    let spread = indent == 0 || needs_lines(value);
+    match value {
+        Value::Array(items) if spread => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&pad(indent + 1));
+                write_tidy(item, indent + 1, out);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push(']');
+        }
+        Value::Object(fields) if spread => {
+            out.push_str("{\n");
+            for (i, (name, item)) in fields.iter().enumerate() {
+                out.push_str(&format!(
+                    "{}{}: ",
+                    pad(indent + 1),
+                    Value::from(name.as_str())
+                ));
+                write_tidy(item, indent + 1, out);
+                out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push('}');
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(compact).collect();
+            out.push_str(&format!("[{}]", items.join(", ")));
+        }
+        _ => out.push_str(&compact(value)),
+    }
+}
+
+/// Lists of anything but numbers go over several lines, and so do objects
+/// holding such lists.
+fn needs_lines(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => items.iter().any(|item| !item.is_number()),
+        Value::Object(fields) => fields.values().any(needs_lines),
+        _ => false,
+    }
+}
+
+/// `value` on one line.
+fn compact(value: &Value) -> String {
+    match value {
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(compact).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, item)| format!("{}: {}", Value::from(name.as_str()), compact(item)))
+                .collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        // The game's numbers are f32s, so they're written as such: 0.15
+        // rather than 0.15000000596046448, and 100 rather than 100.0
+        Value::Number(n) if n.is_f64() => format!("{}", n.as_f64().unwrap_or(0.0) as f32),
+        _ => value.to_string(),
+    }
+}
+
+pub fn load_json<T: DeserializeOwned>(file: &Path) -> Result<T, DataError> {
+    let text = fs::read_to_string(file).map_err(|e| DataError::new(file, e.to_string()))?;
+    parse_json(file, &text)
+}
+
+/// `load_json`, then `check` on what was loaded. Errors from `check` point
+/// at the line of their field, like errors from parsing do.
+pub fn load_json_checked<T: DeserializeOwned>(
+    file: &Path,
+    check: impl FnOnce(&mut T) -> Result<(), DataError>,
+) -> Result<T, DataError> {
+    let text = fs::read_to_string(file).map_err(|e| DataError::new(file, e.to_string()))?;
+    let mut value = parse_json(file, &text)?;
+    check(&mut value).map_err(|e| e.locate(&text))?;
+    Ok(value)
+}
+
+pub fn parse_json<T: DeserializeOwned>(file: &Path, text: &str) -> Result<T, DataError> {
+    let de = &mut serde_json::Deserializer::from_str(text);
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let path = e.path().to_string();
+        let inner = e.into_inner();
+        // serde_json appends " at line X column Y", which we report separately
+        let message = inner.to_string();
+        let suffix = format!(" at line {} column {}", inner.line(), inner.column());
+        let message = message.strip_suffix(&suffix).unwrap_or(&message);
+        DataError {
+            file: file.to_path_buf(),
+            line: Some(inner.line()),
+            column: Some(inner.column()),
+            field: (path != ".").then_some(path),
+            message: message.to_string(),
+        }
+    })
+}
+
+/// The byte offset of the value at `field` (a path like
+/// `enemies[1].right_bound`, as in DataError) in the JSON `text`.
+fn find_field(text: &str, field: &str) -> Option<usize> {
+    let mut scan = Scanner {
+        text: text.as_bytes(),
+        at: 0,
+    };
+    scan.skip_space();
+    for part in field.split('.') {
+        let (name, indices) = part.split_once('[').unwrap_or((part, ""));
+        if !name.is_empty() {
+            scan.enter_field(name)?;
+        }
+        for index in indices.split('[') {
+            if let Some(index) = index.strip_suffix(']') {
+                scan.enter_item(index.parse().ok()?)?;
+            }
+        }
+    }
+    Some(scan.at)
+}
+
+/// Just enough of a JSON reader to walk to a value and skip over others.
+struct Scanner<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.at).copied()
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.at += 1;
+        }
+    }
+
+    /// Steps over `c` and any space after it.
+    fn expect(&mut self, c: u8) -> Option<()> {
+        (self.peek()? == c).then_some(())?;
+        self.at += 1;
+        self.skip_space();
+        Some(())
+    }
+
+    /// A string's contents, escapes and all.
+    fn string(&mut self) -> Option<&str> {
+        (self.peek()? == b'"').then_some(())?;
+        let start = self.at + 1;
+        self.at = start;
+        loop {
+            match self.peek()? {
+                b'"' => break,
+                b'\\' => self.at += 2,
+                _ => self.at += 1,
+            }
+        }
+        let contents = std::str::from_utf8(&self.text[start..self.at]).ok()?;
+        self.at += 1;
+        Some(contents)
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        match self.peek()? {
+            b'"' => {
+                self.string()?;
+            }
+            b'{' | b'[' => {
+                let mut depth = 0;
+                loop {
+                    match self.peek()? {
+                        b'"' => {
+                            self.string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+                    self.at += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                while self
+                    .peek()
+                    .is_some_and(|c| !matches!(c, b',' | b'}' | b']') && !c.is_ascii_whitespace())
+                {
+                    self.at += 1;
+                }
+            }
+        }
+        self.skip_space();
+        Some(())
+    }
+
+    /// Moves from the start of an object to the value of its field `name`.
+    fn enter_field(&mut self, name: &str) -> Option<()> {
+        self.expect(b'{')?;
+        loop {
+            let key = self.string()?;
+            let found = key == name;
+            self.skip_space();
+            self.expect(b':')?;
+            if found {
+                return Some(());
+            }
+            self.skip_value()?;
+            self.expect(b',')?;
+        }
+    }
+
+    /// Moves from the start of an array to its item `index`.
+    fn enter_item(&mut self, index: usize) -> Option<()> {
+        self.expect(b'[')?;
+        for _ in 0..index {
+            self.skip_value()?;
+            self.expect(b',')?;
+        }
+        (self.peek()? != b']').then_some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_located() {
+        let text = r#"{
+    "platforms": [[0, 400, 1000, 40]],
+    "enemies": [
+        { "pos": [320, 270], "left_bound": 300, "right_bound": 390 },
+        { "pos": [600, 270], "left_bound": 700,
+          "right_bound": 650 }
+    ],
+    "camera": { "dead_zone": [-1, 0] }
+}"#;
+        let file = Path::new("level.json");
+        let at = |field: &str| {
+            let err = DataError::field(file, field, "bad").locate(text);
+            err.line.zip(err.column)
+        };
+        assert_eq!(at("enemies[1].right_bound"), Some((6, 26)));
+        assert_eq!(at("enemies[0]"), Some((4, 9)));
+        assert_eq!(at("camera.dead_zone"), Some((8, 30)));
+        assert_eq!(at("platforms[0]"), Some((2, 19)));
+        assert_eq!(at("enemies[2].pos"), None);
+        assert_eq!(at("goal_x"), None);
+    }
+
+    #[test]
+    fn saving_replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!("adventure-data-{}", std::process::id()));
+        let file = dir.join("save.json");
+        save_json(&file, &vec![1, 2]).unwrap();
+        save_json(&file, &vec![3]).unwrap();
+        assert_eq!(load_json::<Vec<i32>>(&file).unwrap(), vec![3]);
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["save.json"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,877 @@
+/*
+ The level editor, opened from the game with the Edit key. It works on a
+ copy of the current level: things are picked and dragged with the mouse,
+ new ones placed at the cursor with the number keys, and every change can
+ be undone. Play-testing hands the copy back to the World; saving writes
+ it to the level's file.
+
+ Mouse: left button selects and drags (the square handles resize
+ platforms, move enemies' patrol ends and moving platforms' path points),
+ right button pans.
+ Keys: 1-8 place a platform, one-way platform, enemy, bonus, power-up,
+ moving platform, hazard or checkpoint; Tab changes the selected thing's
+ kind (an enemy's archetype, for one);
+ P adds a point to the selected moving platform's path; B takes away the
+ selected enemy's patrol bounds; Delete removes the selected thing;
+ G toggles grid snapping and [ ] change the grid size; arrow keys pan;
+ Ctrl+Z / Ctrl+Y undo and redo; Ctrl+S saves; T play-tests from the
+ cursor.
+*/
+
+use std::path::PathBuf;
+
+use macroquad::prelude::*;
+
+use crate::BONUS_SIZE;
+use crate::archetype::Archetypes;
+use crate::data;
+use crate::entities::{Bonus, Checkpoint, Enemy, PowerUp, PowerUpType};
+use crate::input::{Action, Controls};
+use crate::level::{self, Hazard, HazardKind, Level, Platform};
+use crate::moving_platform::{MovingPlatform, PathMode};
+use crate::render;
+use crate::sprites::Sprites;
+use crate::tiled;
+use crate::{PLAYER_HEIGHT, PLAYER_WIDTH, POWERUP_SIZE};
+
+const GRID_SIZES: [f32; 4] = [5.0, 10.0, 20.0, 40.0];
+const HANDLE_SIZE: f32 = 10.0;
+const PAN_SPEED: f32 = 600.0;
+/// How close to the goal line a click has to be to pick it up.
+const GOAL_REACH: f32 = 6.0;
+
+/// Something in the level that can be selected.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Item {
+    Platform(usize),
+    MovingPlatform(usize),
+    Hazard(usize),
+    Enemy(usize),
+    Bonus(usize),
+    PowerUp(usize),
+    Checkpoint(usize),
+    Start,
+    Goal,
+}
+
+/// Which part of an item is being dragged.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Handle {
+    Body,
+    /// A platform's bottom right corner.
+    Resize,
+    LeftBound,
+    RightBound,
+    /// Where a moving platform's top left is at point `i` of its path.
+    PathPoint(usize),
+}
+
+struct Drag {
+    item: Item,
+    handle: Handle,
+    /// Where the item was grabbed, relative to its top left.
+    grab: Vec2,
+    /// The cursor when the button went down. Nothing moves until the
+    /// cursor does, so a click only selects.
+    from: Vec2,
+    started: bool,
+    /// The level as it was before the drag, until it's been put on the
+    /// undo stack by the first change.
+    before: Option<Level>,
+}
+
+/// What the game should do after an editor frame.
+pub enum Command {
+    /// Play the edited level, from its start or with the player's feet at
+    /// the given point.
+    PlayTest(Option<Vec2>),
+}
+
+pub struct Editor {
+    /// Which of the World's levels is being edited.
+    pub index: usize,
+    file: Option<PathBuf>,
+    pub level: Level,
+    /// The level as it is in its file, to go back to when the edits are
+    /// dropped.
+    pub saved: Level,
+    /// Top left of the view, in level coordinates.
+    view: Vec2,
+    selected: Option<Item>,
+    drag: Option<Drag>,
+    /// Where a right-button pan started: the cursor on screen and the view.
+    pan: Option<(Vec2, Vec2)>,
+    snap: bool,
+    grid: usize,
+    undo: Vec<Level>,
+    redo: Vec<Level>,
+    /// Whether there are changes that haven't been saved.
+    dirty: bool,
+    /// Whether leaving has been asked for once already, with unsaved
+    /// changes, since the last change.
+    quit_warned: bool,
+    /// The last save's outcome, shown at the bottom.
+    status: String,
+    /// The kinds of enemy there are to place.
+    archetypes: Archetypes,
+}
+
+impl Editor {
+    /// Edits a copy of `level`, which is saved to `file`, starting with the
+    /// view at `view`.
+    pub fn new(
+        index: usize,
+        file: Option<PathBuf>,
+        level: Level,
+        view: Vec2,
+        archetypes: Archetypes,
+    ) -> Self {
+        Editor {
+            archetypes,
+            index,
+            file,
+            saved: level.clone(),
+            level,
+            view,
+            selected: None,
+            drag: None,
+            pan: None,
+            snap: true,
+            grid: 2,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            dirty: false,
+            quit_warned: false,
+            status: String::new(),
+        }
+    }
+
+    /// Handles one frame of mouse and keyboard input.
+    pub fn update(&mut self) -> Option<Command> {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let cursor = self.cursor();
+
+        if ctrl {
+            if is_key_pressed(KeyCode::Z) && !shift {
+                self.undo();
+            } else if is_key_pressed(KeyCode::Y) || (is_key_pressed(KeyCode::Z) && shift) {
+                self.redo();
+            } else if is_key_pressed(KeyCode::S) {
+                self.save();
+            }
+            return None;
+        }
+
+        self.update_view();
+        self.update_mouse(cursor);
+
+        if is_key_pressed(KeyCode::T) {
+            return Some(Command::PlayTest(Some(cursor)));
+        }
+        if is_key_pressed(KeyCode::G) {
+            self.snap = !self.snap;
+        }
+        if is_key_pressed(KeyCode::LeftBracket) {
+            self.grid = self.grid.saturating_sub(1);
+        }
+        if is_key_pressed(KeyCode::RightBracket) {
+            self.grid = (self.grid + 1).min(GRID_SIZES.len() - 1);
+        }
+        if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+            self.delete_selected();
+        }
+        if is_key_pressed(KeyCode::Tab) {
+            self.change_kind();
+        }
+        if is_key_pressed(KeyCode::P) {
+            self.add_path_point(self.snapped(cursor));
+        }
+        if is_key_pressed(KeyCode::B) {
+            self.clear_bounds();
+        }
+        let places = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+        ];
+        if let Some(what) = places.iter().position(|&key| is_key_pressed(key)) {
+            self.place(what, self.snapped(cursor));
+        }
+        None
+    }
+
+    /// The mouse, in level coordinates.
+    fn cursor(&self) -> Vec2 {
+        Vec2::from(mouse_position()) + self.view
+    }
+
+    fn snapped(&self, pos: Vec2) -> Vec2 {
+        vec2(self.snap_value(pos.x), self.snap_value(pos.y))
+    }
+
+    fn snap_value(&self, v: f32) -> f32 {
+        if self.snap {
+            let grid = GRID_SIZES[self.grid];
+            (v / grid).round() * grid
+        } else {
+            v.round()
+        }
+    }
+
+    fn update_view(&mut self) {
+        let mut dir = Vec2::ZERO;
+        if is_key_down(KeyCode::Left) {
+            dir.x -= 1.0;
+        }
+        if is_key_down(KeyCode::Right) {
+            dir.x += 1.0;
+        }
+        if is_key_down(KeyCode::Up) {
+            dir.y -= 1.0;
+        }
+        if is_key_down(KeyCode::Down) {
+            dir.y += 1.0;
+        }
+        self.view += dir * PAN_SPEED * get_frame_time();
+
+        let mouse = Vec2::from(mouse_position());
+        if is_mouse_button_pressed(MouseButton::Right) {
+            self.pan = Some((mouse, self.view));
+        }
+        if let Some((from, view)) = self.pan {
+            self.view = view - (mouse - from);
+            if !is_mouse_button_down(MouseButton::Right) {
+                self.pan = None;
+            }
+        }
+    }
+
+    fn update_mouse(&mut self, cursor: Vec2) {
+        if is_mouse_button_pressed(MouseButton::Left) {
+            self.drag = self.pick(cursor).map(|(item, handle)| Drag {
+                item,
+                handle,
+                grab: cursor - self.origin(item),
+                from: cursor,
+                started: false,
+                before: Some(self.level.clone()),
+            });
+            self.selected = self.drag.as_ref().map(|drag| drag.item);
+        }
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.drag = None;
+        }
+
+        let Some(drag) = &mut self.drag else {
+            return;
+        };
+        drag.started |= cursor != drag.from;
+        if !drag.started {
+            return;
+        }
+        let (item, handle, grab) = (drag.item, drag.handle, drag.grab);
+        let at = self.snapped(cursor);
+        let changed = match handle {
+            Handle::Body => self.move_to(item, self.snapped(cursor - grab)),
+            Handle::Resize => self.resize(item, at),
+            Handle::LeftBound | Handle::RightBound => self.move_bound(item, handle, at.x),
+            Handle::PathPoint(point) => self.move_path_point(item, point, at),
+        };
+        if changed && let Some(before) = self.drag.as_mut().and_then(|d| d.before.take()) {
+            self.push_undo(before);
+        }
+    }
+
+    /// The item (and which part of it) under `pos`, trying the selected
+    /// item's handles first and then whatever's drawn on top.
+    fn pick(&self, pos: Vec2) -> Option<(Item, Handle)> {
+        if let Some(item) = self.selected {
+            for (handle, at) in self.handles(item) {
+                if handle_rect(at).contains(pos) {
+                    return Some((item, handle));
+                }
+            }
+        }
+        let level = &self.level;
+        let mut items = vec![Item::Start];
+        items.extend((0..level.checkpoints.len()).rev().map(Item::Checkpoint));
+        items.extend((0..level.bonuses.len()).rev().map(Item::Bonus));
+        items.extend((0..level.powerups.len()).rev().map(Item::PowerUp));
+        items.extend((0..level.enemies.len()).rev().map(Item::Enemy));
+        items.extend(
+            (0..level.moving_platforms.len())
+                .rev()
+                .map(Item::MovingPlatform),
+        );
+        items.extend((0..level.platforms.len()).rev().map(Item::Platform));
+        items.extend((0..level.hazards.len()).rev().map(Item::Hazard));
+        if let Some(item) = items
+            .into_iter()
+            .find(|&item| self.rect(item).contains(pos))
+        {
+            return Some((item, Handle::Body));
+        }
+        ((pos.x - level.goal_x).abs() <= GOAL_REACH).then_some((Item::Goal, Handle::Body))
+    }
+
+    /// The area `item` covers. The goal line has no height, so it's given
+    /// the height of the screen.
+    fn rect(&self, item: Item) -> Rect {
+        let level = &self.level;
+        let at = |pos: Vec2, w, h| Rect::new(pos.x, pos.y, w, h);
+        match item {
+            Item::Platform(i) => level.platforms[i].rect,
+            Item::MovingPlatform(i) => level.moving_platforms[i].rect,
+            Item::Hazard(i) => level.hazards[i].rect,
+            Item::Enemy(i) => level.enemies[i].rect(),
+            Item::Bonus(i) => at(level.bonuses[i].pos, BONUS_SIZE, BONUS_SIZE),
+            Item::PowerUp(i) => at(level.powerups[i].pos, POWERUP_SIZE, POWERUP_SIZE),
+            Item::Checkpoint(i) => level.checkpoints[i].rect(),
+            Item::Start => at(level.start, PLAYER_WIDTH, PLAYER_HEIGHT),
+            Item::Goal => Rect::new(level.goal_x, self.view.y, 0.0, screen_height()),
+        }
+    }
+
+    fn origin(&self, item: Item) -> Vec2 {
+        self.rect(item).point()
+    }
+
+    /// The handles `item` has besides its body, and where they are.
+    fn handles(&self, item: Item) -> Vec<(Handle, Vec2)> {
+        match item {
+            Item::Platform(_) | Item::Hazard(_) => {
+                let r = self.rect(item);
+                vec![(Handle::Resize, vec2(r.right(), r.bottom()))]
+            }
+            Item::MovingPlatform(i) => {
+                let moving = &self.level.moving_platforms[i];
+                let r = moving.rect;
+                let mut handles = vec![(Handle::Resize, vec2(r.right(), r.bottom()))];
+                for (point, &offset) in moving.path.iter().enumerate() {
+                    handles.push((Handle::PathPoint(point), r.point() + offset));
+                }
+                handles
+            }
+            Item::Enemy(i) => {
+                let enemy = &self.level.enemies[i];
+                let r = enemy.rect();
+                let y = r.center().y;
+                let (left, right) = patrol(enemy);
+                vec![
+                    (Handle::LeftBound, vec2(left, y)),
+                    (Handle::RightBound, vec2(right + r.w, y)),
+                ]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Moves `item`'s top left to `pos`; an enemy's patrol moves with it.
+    /// Returns whether anything changed.
+    fn move_to(&mut self, item: Item, pos: Vec2) -> bool {
+        let level = &mut self.level;
+        let old = match item {
+            Item::Platform(i) => level.platforms[i].rect.point(),
+            Item::MovingPlatform(i) => level.moving_platforms[i].rect.point(),
+            Item::Hazard(i) => level.hazards[i].rect.point(),
+            Item::Enemy(i) => level.enemies[i].pos,
+            Item::Bonus(i) => level.bonuses[i].pos,
+            Item::PowerUp(i) => level.powerups[i].pos,
+            Item::Checkpoint(i) => level.checkpoints[i].pos,
+            Item::Start => level.start,
+            Item::Goal => vec2(level.goal_x, 0.0),
+        };
+        let pos = if item == Item::Goal {
+            vec2(pos.x, 0.0)
+        } else {
+            pos
+        };
+        if pos == old {
+            return false;
+        }
+        match item {
+            Item::Platform(i) => level.platforms[i].rect.move_to(pos),
+            Item::MovingPlatform(i) => level.moving_platforms[i].rect.move_to(pos),
+            Item::Hazard(i) => level.hazards[i].rect.move_to(pos),
+            Item::Enemy(i) => {
+                let enemy = &mut level.enemies[i];
+                let dx = pos.x - enemy.pos.x;
+                enemy.pos = pos;
+                for bound in [&mut enemy.left_bound, &mut enemy.right_bound]
+                    .into_iter()
+                    .flatten()
+                {
+                    *bound += dx;
+                }
+            }
+            Item::Bonus(i) => level.bonuses[i].pos = pos,
+            Item::PowerUp(i) => level.powerups[i].pos = pos,
+            Item::Checkpoint(i) => level.checkpoints[i].pos = pos,
+            Item::Start => level.start = pos,
+            Item::Goal => level.goal_x = pos.x,
+        }
+        true
+    }
+
+    /// Moves a platform's or hazard's bottom right corner to `corner`.
+    fn resize(&mut self, item: Item, corner: Vec2) -> bool {
+        let rect = match item {
+            Item::Platform(i) => &mut self.level.platforms[i].rect,
+            Item::MovingPlatform(i) => &mut self.level.moving_platforms[i].rect,
+            Item::Hazard(i) => &mut self.level.hazards[i].rect,
+            _ => return false,
+        };
+        let min = GRID_SIZES[0];
+        let w = (corner.x - rect.x).max(min);
+        let h = (corner.y - rect.y).max(min);
+        let changed = w != rect.w || h != rect.h;
+        rect.w = w;
+        rect.h = h;
+        changed
+    }
+
+    /// Moves one end of an enemy's patrol to `x`, giving it one if it had
+    /// none; the enemy itself is kept inside it.
+    fn move_bound(&mut self, item: Item, handle: Handle, x: f32) -> bool {
+        let Item::Enemy(i) = item else {
+            return false;
+        };
+        let enemy = &mut self.level.enemies[i];
+        let (old_left, old_right) = (enemy.left_bound, enemy.right_bound);
+        let left = enemy.left_bound.unwrap_or(f32::NEG_INFINITY);
+        let right = enemy.right_bound.unwrap_or(f32::INFINITY);
+        if handle == Handle::LeftBound {
+            enemy.left_bound = Some(x.min(right));
+        } else {
+            enemy.right_bound = Some((x - enemy.rect().w).max(left));
+        }
+        let left = enemy.left_bound.unwrap_or(f32::NEG_INFINITY);
+        let right = enemy.right_bound.unwrap_or(f32::INFINITY);
+        enemy.pos.x = enemy.pos.x.clamp(left, right);
+        enemy.left_bound != old_left || enemy.right_bound != old_right
+    }
+
+    /// Takes away the selected enemy's patrol bounds, leaving it to patrol
+    /// whatever it stands on.
+    fn clear_bounds(&mut self) {
+        let Some(Item::Enemy(i)) = self.selected else {
+            return;
+        };
+        let enemy = &self.level.enemies[i];
+        if enemy.left_bound.is_none() && enemy.right_bound.is_none() {
+            return;
+        }
+        self.push_undo(self.level.clone());
+        let enemy = &mut self.level.enemies[i];
+        enemy.left_bound = None;
+        enemy.right_bound = None;
+    }
+
+    /// Moves point `point` of a moving platform's path so that the platform's
+    /// top left gets to `pos` there.
+    fn move_path_point(&mut self, item: Item, point: usize, pos: Vec2) -> bool {
+        let Item::MovingPlatform(i) = item else {
+            return false;
+        };
+        let moving = &mut self.level.moving_platforms[i];
+        let offset = pos - moving.rect.point();
+        let changed = moving.path[point] != offset;
+        moving.path[point] = offset;
+        changed
+    }
+
+    /// Adds a point at `pos` to the end of the selected moving platform's
+    /// path.
+    fn add_path_point(&mut self, pos: Vec2) {
+        let Some(Item::MovingPlatform(i)) = self.selected else {
+            return;
+        };
+        self.push_undo(self.level.clone());
+        let moving = &mut self.level.moving_platforms[i];
+        let offset = pos - moving.rect.point();
+        moving.path.push(offset);
+    }
+
+    /// Adds thing number `what` (as on the number keys) at `pos`.
+    fn place(&mut self, what: usize, pos: Vec2) {
+        let before = self.level.clone();
+        let level = &mut self.level;
+        let item = match what {
+            0 | 1 => {
+                level.platforms.push(Platform {
+                    rect: Rect::new(pos.x, pos.y, 120.0, 20.0),
+                    one_way: what == 1,
+                });
+                Item::Platform(level.platforms.len() - 1)
+            }
+            2 => {
+                let kind = match self.archetypes.get("walker") {
+                    Some(_) => "walker",
+                    None => self.archetypes.names().next().unwrap_or_default(),
+                };
+                let Some(archetype) = self.archetypes.get(kind) else {
+                    return;
+                };
+                let enemy = Enemy::new(kind, archetype, pos);
+                level.enemies.push(enemy);
+                Item::Enemy(level.enemies.len() - 1)
+            }
+            3 => {
+                level.bonuses.push(Bonus {
+                    pos,
+                    collected: false,
+                });
+                Item::Bonus(level.bonuses.len() - 1)
+            }
+            4 => {
+                level.powerups.push(PowerUp {
+                    pos,
+                    kind: PowerUpType::Health,
+                    collected: false,
+                });
+                Item::PowerUp(level.powerups.len() - 1)
+            }
+            5 => {
+                let rect = Rect::new(pos.x, pos.y, 120.0, 20.0);
+                let path = vec![vec2(160.0, 0.0)];
+                level.moving_platforms.push(MovingPlatform::new(rect, path));
+                Item::MovingPlatform(level.moving_platforms.len() - 1)
+            }
+            6 => {
+                level.hazards.push(Hazard {
+                    rect: Rect::new(pos.x, pos.y, 80.0, 20.0),
+                    kind: HazardKind::Spikes,
+                    damage: None,
+                });
+                Item::Hazard(level.hazards.len() - 1)
+            }
+            _ => {
+                level.checkpoints.push(Checkpoint {
+                    pos,
+                    restore: false,
+                    reached: false,
+                });
+                Item::Checkpoint(level.checkpoints.len() - 1)
+            }
+        };
+        // Only now that there's something new to undo
+        self.push_undo(before);
+        self.selected = Some(item);
+    }
+
+    fn delete_selected(&mut self) {
+        let Some(item) = self.selected else {
+            return;
+        };
+        if matches!(item, Item::Start | Item::Goal) {
+            return;
+        }
+        self.push_undo(self.level.clone());
+        let level = &mut self.level;
+        match item {
+            Item::Platform(i) => {
+                level.platforms.remove(i);
+            }
+            Item::MovingPlatform(i) => {
+                level.moving_platforms.remove(i);
+            }
+            Item::Hazard(i) => {
+                level.hazards.remove(i);
+            }
+            Item::Enemy(i) => {
+                level.enemies.remove(i);
+            }
+            Item::Bonus(i) => {
+                level.bonuses.remove(i);
+            }
+            Item::PowerUp(i) => {
+                level.powerups.remove(i);
+            }
+            Item::Checkpoint(i) => {
+                level.checkpoints.remove(i);
+            }
+            Item::Start | Item::Goal => {}
+        }
+        self.selected = None;
+        self.drag = None;
+    }
+
+    /// Platforms switch between solid and one-way, moving platforms go
+    /// through solid and one-way, ping-pong and loop, enemies through the
+    /// archetypes, checkpoints between restoring progress and not,
+    /// and hazards and power-ups go through the kinds.
+    fn change_kind(&mut self) {
+        let Some(item) = self.selected else {
+            return;
+        };
+        if !matches!(
+            item,
+            Item::Platform(_)
+                | Item::MovingPlatform(_)
+                | Item::Hazard(_)
+                | Item::Enemy(_)
+                | Item::PowerUp(_)
+                | Item::Checkpoint(_)
+        ) {
+            return;
+        }
+        self.push_undo(self.level.clone());
+        let level = &mut self.level;
+        match item {
+            Item::Platform(i) => level.platforms[i].one_way ^= true,
+            Item::MovingPlatform(i) => {
+                let moving = &mut level.moving_platforms[i];
+                moving.one_way ^= true;
+                if !moving.one_way {
+                    moving.mode = match moving.mode {
+                        PathMode::PingPong => PathMode::Loop,
+                        PathMode::Loop => PathMode::PingPong,
+                    };
+                }
+            }
+            Item::Hazard(i) => {
+                let hazard = &mut level.hazards[i];
+                hazard.kind = match hazard.kind {
+                    HazardKind::Spikes => HazardKind::Lava,
+                    HazardKind::Lava => HazardKind::Pit,
+                    HazardKind::Pit => HazardKind::Spikes,
+                };
+            }
+            Item::Enemy(i) => {
+                let enemy = &mut level.enemies[i];
+                let names: Vec<&str> = self.archetypes.names().collect();
+                let next = names
+                    .iter()
+                    .position(|&name| name == enemy.kind)
+                    .map_or(0, |i| (i + 1) % names.len());
+                if let Some(archetype) = self.archetypes.get(names[next]) {
+                    enemy.kind = names[next].to_string();
+                    enemy.set_archetype(archetype);
+                }
+            }
+            Item::Checkpoint(i) => level.checkpoints[i].restore ^= true,
+            Item::PowerUp(i) => {
+                let powerup = &mut level.powerups[i];
+                powerup.kind = match powerup.kind {
+                    PowerUpType::Health => PowerUpType::Speed,
+                    PowerUpType::Speed => PowerUpType::Invincibility,
+                    PowerUpType::Invincibility => PowerUpType::HighJump,
+                    PowerUpType::HighJump => PowerUpType::SpreadShot,
+                    PowerUpType::SpreadShot => PowerUpType::RapidFire,
+                    PowerUpType::RapidFire => PowerUpType::Laser,
+                    PowerUpType::Laser => PowerUpType::Health,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    /// Remembers `before` as the state to go back to, as a change is made.
+    fn push_undo(&mut self, before: Level) {
+        self.undo.push(before);
+        self.redo.clear();
+        self.dirty = true;
+        self.quit_warned = false;
+    }
+
+    fn undo(&mut self) {
+        if let Some(level) = self.undo.pop() {
+            self.redo.push(std::mem::replace(&mut self.level, level));
+            self.selected = None;
+            self.drag = None;
+            self.dirty = true;
+            self.quit_warned = false;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(level) = self.redo.pop() {
+            self.undo.push(std::mem::replace(&mut self.level, level));
+            self.selected = None;
+            self.drag = None;
+            self.dirty = true;
+            self.quit_warned = false;
+        }
+    }
+
+    /// Whether it's all right to leave the editor, which drops unsaved
+    /// changes. With some, the first ask only warns (naming `quit_keys`)
+    /// and asking again is the go-ahead.
+    pub fn may_quit(&mut self, quit_keys: &str) -> bool {
+        if !self.dirty || self.quit_warned {
+            return true;
+        }
+        self.quit_warned = true;
+        self.status = format!(
+            "unsaved changes: {} again to drop them, Ctrl+S to save",
+            quit_keys
+        );
+        false
+    }
+
+    fn save(&mut self) {
+        self.status = match &self.file {
+            None => "nowhere to save: the level has no file".to_string(),
+            Some(file) if tiled::is_map(file) => {
+                format!("{} is a Tiled map; edit it in Tiled", file.display())
+            }
+            Some(file) => match level::validate(file, &self.level)
+                .and_then(|()| data::save_json_tidy(file, &self.level))
+            {
+                Ok(()) => {
+                    self.dirty = false;
+                    self.saved = self.level.clone();
+                    format!("saved {}", file.display())
+                }
+                Err(err) => format!("not saved: {}", err),
+            },
+        };
+    }
+
+    /// Draws the level and the help, naming the keys from `controls`.
+    pub fn draw(&self, sprites: &Sprites, controls: &Controls) {
+        let view = self.view;
+        render::draw_level(&self.level, sprites, view);
+
+        if self.snap {
+            let grid = GRID_SIZES[self.grid].max(20.0);
+            let color = Color::new(0.0, 0.0, 0.0, 0.08);
+            let mut x = -(view.x.rem_euclid(grid));
+            while x < screen_width() {
+                draw_line(x, 0.0, x, screen_height(), 1.0, color);
+                x += grid;
+            }
+            let mut y = -(view.y.rem_euclid(grid));
+            while y < screen_height() {
+                draw_line(0.0, y, screen_width(), y, 1.0, color);
+                y += grid;
+            }
+        }
+
+        let level = &self.level;
+        let start = level.start - view;
+        draw_rectangle_lines(start.x, start.y, PLAYER_WIDTH, PLAYER_HEIGHT, 2.0, BLUE);
+        draw_text("start", start.x, start.y - 4.0, 20.0, BLUE);
+        let goal_x = level.goal_x - view.x;
+        draw_line(goal_x, 0.0, goal_x, screen_height(), 2.0, RED);
+        draw_text("goal", goal_x + 4.0, 40.0, 20.0, RED);
+        let kill_y = level.kill_y() - view.y;
+        draw_line(0.0, kill_y, screen_width(), kill_y, 1.0, PURPLE);
+        draw_text("fall limit", 10.0, kill_y - 4.0, 20.0, PURPLE);
+        for hazard in level.hazards.iter().filter(|h| h.kind == HazardKind::Pit) {
+            let r = hazard.rect.offset(-view);
+            draw_rectangle(r.x, r.y, r.w, r.h, Color::new(0.5, 0.0, 0.5, 0.2));
+            draw_text("pit", r.x + 4.0, r.y + 18.0, 20.0, PURPLE);
+        }
+        for enemy in &level.enemies {
+            let r = enemy.rect().offset(-view);
+            let y = r.center().y;
+            if enemy.left_bound.is_some() || enemy.right_bound.is_some() {
+                let (left, right) = patrol(enemy);
+                draw_line(left - view.x, y, right + r.w - view.x, y, 1.0, MAROON);
+            }
+            draw_text(&enemy.kind, r.x, r.y - 4.0, 20.0, MAROON);
+        }
+        for checkpoint in &level.checkpoints {
+            let pos = checkpoint.pos - view;
+            let label = if checkpoint.restore {
+                "checkpoint (restores)"
+            } else {
+                "checkpoint"
+            };
+            draw_text(label, pos.x, pos.y - 4.0, 20.0, DARKGRAY);
+        }
+        for moving in &level.moving_platforms {
+            let r = moving.rect;
+            let mut points: Vec<Vec2> = (0..=moving.path.len()).map(|i| moving.point(i)).collect();
+            if moving.mode == PathMode::Loop {
+                points.push(Vec2::ZERO);
+            }
+            for pair in points.windows(2) {
+                let (a, b) = (r.center() + pair[0] - view, r.center() + pair[1] - view);
+                draw_line(a.x, a.y, b.x, b.y, 1.0, BROWN);
+            }
+            for &offset in &moving.path {
+                let ghost = r.offset(offset - view);
+                draw_rectangle_lines(ghost.x, ghost.y, ghost.w, ghost.h, 1.0, BROWN);
+            }
+        }
+
+        if let Some(item) = self.selected {
+            let r = self.rect(item);
+            draw_rectangle_lines(r.x - view.x, r.y - view.y, r.w, r.h, 3.0, YELLOW);
+            for (_, at) in self.handles(item) {
+                let h = handle_rect(at - view);
+                draw_rectangle(h.x, h.y, h.w, h.h, YELLOW);
+                draw_rectangle_lines(h.x, h.y, h.w, h.h, 1.0, BLACK);
+            }
+        }
+
+        let name = self
+            .file
+            .as_ref()
+            .map_or("(no file)".to_string(), |f| f.display().to_string());
+        let grid = if self.snap {
+            format!("grid {}", GRID_SIZES[self.grid])
+        } else {
+            "no grid".to_string()
+        };
+        let cursor = self.snapped(self.cursor());
+        let lines = [
+            format!(
+                "EDITING {}{}   {}   cursor {} {}",
+                name,
+                if self.dirty { " *" } else { "" },
+                grid,
+                cursor.x,
+                cursor.y
+            ),
+            "1-8 place: platform, one-way, enemy, bonus, power-up, moving, hazard, checkpoint"
+                .to_string(),
+            "Tab kind   P path point   B clear patrol   Del delete   G grid   \
+             [ ] grid size   arrows/right drag pan"
+                .to_string(),
+            format!(
+                "Ctrl+Z/Y undo/redo   Ctrl+S save   T test from cursor   {} test from start",
+                controls.key_names(Action::Edit)
+            ),
+        ];
+        draw_rectangle(
+            0.0,
+            0.0,
+            screen_width(),
+            lines.len() as f32 * 22.0 + 10.0,
+            Color::new(1.0, 1.0, 1.0, 0.8),
+        );
+        for (i, line) in lines.iter().enumerate() {
+            draw_text(line, 10.0, 22.0 + i as f32 * 22.0, 22.0, BLACK);
+        }
+        if !self.status.is_empty() {
+            draw_text(&self.status, 10.0, screen_height() - 12.0, 24.0, DARKBLUE);
+        }
+    }
+}
+
+/// Where an enemy's patrol ends are shown: its bounds, or the enemy itself
+/// for any it doesn't have.
+fn patrol(enemy: &Enemy) -> (f32, f32) {
+    (
+        enemy.left_bound.unwrap_or(enemy.pos.x),
+        enemy.right_bound.unwrap_or(enemy.pos.x),
+    )
+}
+
+fn handle_rect(center: Vec2) -> Rect {
+    Rect::new(
+        center.x - HANDLE_SIZE / 2.0,
+        center.y - HANDLE_SIZE / 2.0,
+        HANDLE_SIZE,
+        HANDLE_SIZE,
+    )
+}
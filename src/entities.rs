@@ -0,0 +1,542 @@
+/*
+ Game objects and their per-frame physics. Nothing in here may touch
+ macroquad's window, graphics or input: drawing lives in render.rs and
+ input arrives as an InputFrame.
+*/
+
+use macroquad::math::{Rect, Vec2, vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::ai::AiState;
+use crate::archetype::{Archetype, Behaviour};
+use crate::physics::{self, Solids};
+use crate::weapon::{Weapon, WeaponKind};
+use crate::world::InputFrame;
+use crate::{
+    BASE_MOVE_SPEED, BONUS_SIZE, GRAVITY, HIGH_JUMP_SPEED, JUMP_SPEED, KNOCKBACK_LIFT,
+    KNOCKBACK_SPEED, KNOCKBACK_TIME, MAX_HEALTH, PLAYER_HEIGHT, PLAYER_WIDTH, POWERUP_SIZE,
+    RUNNING_SPEED, SPEED_BOOST, TIMER_BOOST,
+};
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum PowerUpType {
+    Health,
+    Speed,
+    Invincibility,
+    HighJump, // NEW
+    SpreadShot,
+    RapidFire,
+    Laser,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PowerUp {
+    pub pos: Vec2,
+    pub kind: PowerUpType,
+    #[serde(default)]
+    pub collected: bool,
+}
+
+impl PowerUp {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.x, self.pos.y, POWERUP_SIZE, POWERUP_SIZE)
+    }
+}
+
+/// An enemy in a level. What kind it is (its `kind`, looked up in the
+/// archetypes by Level::set_archetypes) decides everything but where it is
+/// and where it patrols. Without bounds it patrols whatever it's standing
+/// on.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Enemy {
+    pub pos: Vec2,
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    /// Only the direction it sets off in counts; the speed is the
+    /// archetype's.
+    #[serde(default)]
+    pub vel: Vec2,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_bound: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right_bound: Option<f32>,
+    #[serde(default = "default_true")]
+    pub alive: bool,
+    #[serde(skip)]
+    pub archetype: Archetype,
+    #[serde(skip)]
+    pub health: i32,
+    /// Seconds since it was spawned, which flyers bob along to.
+    #[serde(skip)]
+    pub age: f32,
+    /// For a shooter, seconds the player has been in range since the last
+    /// shot.
+    #[serde(skip)]
+    pub charge: f32,
+    #[serde(skip)]
+    pub prev_pos: Vec2,
+    #[serde(skip)]
+    pub facing_right: bool,
+    #[serde(skip)]
+    pub on_ground: bool,
+    #[serde(skip)]
+    pub state: AiState,
+    /// Seconds since it went into `state`.
+    #[serde(skip)]
+    pub state_time: f32,
+    /// Where it last saw the player, and how many seconds ago.
+    #[serde(skip)]
+    pub last_seen: Vec2,
+    #[serde(skip)]
+    pub unseen: f32,
+    /// The x it left its patrol at, to return to.
+    #[serde(skip)]
+    pub home: f32,
+}
+
+fn default_kind() -> String {
+    "walker".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// How far above and below its path a flyer bobs, and how fast (radians a
+/// second).
+const BOB_HEIGHT: f32 = 12.0;
+const BOB_RATE: f32 = 3.0;
+
+impl Enemy {
+    /// A `kind` enemy at `pos`, patrolling whatever it stands on.
+    pub fn new(kind: &str, archetype: &Archetype, pos: Vec2) -> Self {
+        let mut enemy = Enemy {
+            pos,
+            kind: kind.to_string(),
+            vel: Vec2::ZERO,
+            left_bound: None,
+            right_bound: None,
+            alive: true,
+            archetype: Archetype::default(),
+            health: 0,
+            age: 0.0,
+            charge: 0.0,
+            prev_pos: pos,
+            facing_right: true,
+            on_ground: false,
+            state: AiState::Patrol,
+            state_time: 0.0,
+            last_seen: Vec2::ZERO,
+            unseen: 0.0,
+            home: pos.x,
+        };
+        enemy.set_archetype(archetype);
+        enemy
+    }
+
+    /// Makes it one of `archetype`, at full health, setting off the way
+    /// `vel` says.
+    pub fn set_archetype(&mut self, archetype: &Archetype) {
+        self.archetype = archetype.clone();
+        self.health = archetype.health;
+        self.facing_right = self.vel.x >= 0.0;
+    }
+
+    /// Moves it on: it decides what to do about the player (see ai.rs),
+    /// then moves by its archetype's behaviour. `target` is where the
+    /// player is (their centre), if they're around to be noticed.
+    pub fn update(&mut self, dt: f32, solids: &Solids, target: Option<Vec2>) {
+        self.prev_pos = self.pos;
+        if !self.alive {
+            return;
+        }
+        self.age += dt;
+        self.vel.x = self.think(dt, solids, target);
+        if self.vel.x != 0.0 && self.ledge_ahead(dt, solids) {
+            self.blocked(self.vel.x > 0.0);
+        }
+        let archetype = &self.archetype;
+        if archetype.behaviour == Behaviour::Fly {
+            self.vel.y = BOB_HEIGHT * BOB_RATE * (self.age * BOB_RATE).cos();
+        } else {
+            self.vel.y += archetype.gravity * dt;
+        }
+        let size = archetype.size;
+        let contacts = physics::move_and_collide(&mut self.pos, &mut self.vel, size, dt, solids);
+        self.on_ground = contacts.on_ground;
+        if self.archetype.behaviour == Behaviour::Jump && contacts.on_ground {
+            self.vel.y = -self.archetype.jump_speed;
+        }
+
+        // Stop at walls and at the ends of the patrol
+        if let Some(left) = self.left_bound
+            && self.pos.x < left
+        {
+            self.pos.x = left;
+            self.blocked(false);
+        } else if contacts.hit_wall_left {
+            self.blocked(false);
+        }
+        if let Some(right) = self.right_bound
+            && self.pos.x > right
+        {
+            self.pos.x = right;
+            self.blocked(true);
+        } else if contacts.hit_wall_right {
+            self.blocked(true);
+        }
+    }
+
+    /// Whether its next step (or for a jumper, its next hop) would take it
+    /// off the edge of what it's standing on. Flyers don't mind.
+    fn ledge_ahead(&self, dt: f32, solids: &Solids) -> bool {
+        let archetype = &self.archetype;
+        if archetype.behaviour == Behaviour::Fly || !self.on_ground {
+            return false;
+        }
+        let time = if archetype.behaviour == Behaviour::Jump && archetype.gravity > 0.0 {
+            2.0 * archetype.jump_speed / archetype.gravity
+        } else {
+            dt
+        };
+        let reach = self.vel.x.abs() * time;
+        let rect = self.rect();
+        let x = if self.vel.x > 0.0 {
+            rect.right() + reach
+        } else {
+            rect.left() - reach
+        };
+        !physics::ground_at(vec2(x, rect.bottom() + 1.0), solids)
+    }
+
+    pub fn rect(&self) -> Rect {
+        let size = self.archetype.size;
+        Rect::new(self.pos.x, self.pos.y, size.x, size.y)
+    }
+
+    /// For a shooter, the shot it fires at `target` after `dt` more seconds
+    /// attacking, if it's time for one.
+    pub fn shoot_at(&mut self, target: Vec2, dt: f32) -> Option<Bullet> {
+        let center = self.rect().center();
+        let shooter = self.archetype.shooter.as_ref()?;
+        if !self.alive || self.state != AiState::Attack {
+            self.charge = 0.0;
+            return None;
+        }
+        self.charge += dt;
+        if self.charge < shooter.interval {
+            return None;
+        }
+        self.charge = 0.0;
+        let vel = (target - center).normalize_or_zero() * shooter.speed;
+        Some(Bullet::new(center, vel, WeaponKind::Pistol, Team::Enemies))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bonus {
+    pub pos: Vec2,
+    #[serde(default)]
+    pub collected: bool,
+}
+
+impl Bonus {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.x, self.pos.y, BONUS_SIZE, BONUS_SIZE)
+    }
+}
+
+/// A flag that, once the player touches it, is where they come back after
+/// being hurt instead of the level's start.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Checkpoint {
+    /// Where the player respawns (their top left); touching the player-sized
+    /// box here reaches the checkpoint.
+    pub pos: Vec2,
+    /// Whether respawning here also puts the enemies, bonuses, power-ups and
+    /// score back as they were when it was reached.
+    #[serde(default)]
+    pub restore: bool,
+    #[serde(skip)]
+    pub reached: bool,
+}
+
+impl Checkpoint {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.x, self.pos.y, PLAYER_WIDTH, PLAYER_HEIGHT)
+    }
+}
+
+/// Who fired a bullet. Bullets only hurt the other side.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Team {
+    Player,
+    Enemies,
+}
+
+pub struct Bullet {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub alive: bool,
+    pub prev_pos: Vec2,
+    pub team: Team,
+    /// What fired it, which decides the damage it does. Enemies fire
+    /// pistol bullets.
+    pub weapon: WeaponKind,
+    /// How many more times it ricochets off solid geometry instead of
+    /// stopping there.
+    pub bounces: u32,
+    /// The enemies a piercing bullet has already gone through, so it only
+    /// hurts each once.
+    pub hits: Vec<usize>,
+}
+
+impl Bullet {
+    pub fn new(pos: Vec2, vel: Vec2, weapon: WeaponKind, team: Team) -> Self {
+        Bullet {
+            pos,
+            vel,
+            alive: true,
+            prev_pos: pos,
+            team,
+            weapon,
+            bounces: weapon.stats().bounces,
+            hits: Vec::new(),
+        }
+    }
+
+    /// Moves the bullet on, stopping or bouncing it at `solids`. Bullets
+    /// that leave `area` are gone. Returns where it hit something, if it did.
+    pub fn update(&mut self, dt: f32, solids: &Solids, area: Rect) -> Option<Vec2> {
+        self.prev_pos = self.pos;
+        if !self.alive {
+            return None;
+        }
+        // Fired from inside a wall
+        let rect = self.rect();
+        if solids
+            .near(rect)
+            .any(|p| !p.one_way && physics::overlaps(&rect, &p.rect))
+        {
+            self.alive = false;
+            return Some(rect.center());
+        }
+
+        let vel = self.vel;
+        let contacts =
+            physics::move_and_collide(&mut self.pos, &mut self.vel, rect.size(), dt, solids);
+        if !physics::overlaps(&self.rect(), &area) {
+            self.alive = false;
+            return None;
+        }
+        let hit_x = contacts.hit_wall();
+        let hit_y = contacts.on_ground || contacts.hit_ceiling;
+        if !hit_x && !hit_y {
+            return None;
+        }
+        if self.bounces > 0 {
+            self.bounces -= 1;
+            if hit_x {
+                self.vel.x = -vel.x;
+            }
+            if hit_y {
+                self.vel.y = -vel.y;
+            }
+        } else {
+            self.alive = false;
+        }
+        Some(self.rect().center())
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.x, self.pos.y, 10.0, 4.0)
+    }
+}
+
+pub struct Player {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub on_ground: bool,
+    pub facing_right: bool,
+    pub health: i32,
+    pub score: i32,
+    pub alive: bool,
+    pub speed_timer: f32,
+    pub invincible_timer: f32,
+    pub high_jump_timer: f32, // NEW
+    pub prev_pos: Vec2,       // position before the last step, for jump-on detection and drawing
+    pub is_running: bool,
+    /// Seconds left of the hurt and shooting poses. Only for looks.
+    pub hurt_timer: f32,
+    pub shoot_timer: f32,
+    /// Seconds left of the invulnerability after a hit, while the player
+    /// flashes.
+    pub invulnerable_timer: f32,
+    /// Seconds left of being knocked back, when the controls don't steer.
+    pub knockback_timer: f32,
+    /// The weapons the player carries, the pistol first, and which one is
+    /// in hand.
+    pub weapons: Vec<Weapon>,
+    pub weapon: usize,
+}
+
+impl Player {
+    /// A fresh player with full health and no score, standing at `start`.
+    pub fn new(start: Vec2) -> Self {
+        Player {
+            pos: start,
+            vel: Vec2::ZERO,
+            on_ground: false,
+            facing_right: true,
+            health: MAX_HEALTH,
+            score: 0,
+            alive: true,
+            speed_timer: 0.0,
+            invincible_timer: 0.0,
+            high_jump_timer: 0.0,
+            prev_pos: start,
+            is_running: false,
+            hurt_timer: 0.0,
+            shoot_timer: 0.0,
+            invulnerable_timer: 0.0,
+            knockback_timer: 0.0,
+            weapons: vec![Weapon::new(WeaponKind::Pistol)],
+            weapon: 0,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, input: &InputFrame, solids: &Solids) {
+        if !self.alive {
+            return;
+        }
+
+        self.prev_pos = self.pos;
+        let dir = input.move_dir();
+
+        self.is_running = input.run;
+
+        //let move_speed = BASE_MOVE_SPEED * boost + if self.speed_timer > 0.0 { SPEED_BOOST } else { 0.0 };
+
+        let move_speed = if self.is_running {
+            RUNNING_SPEED
+        } else {
+            BASE_MOVE_SPEED
+        } + if self.speed_timer > 0.0 {
+            SPEED_BOOST
+        } else {
+            0.0
+        };
+
+        let knocked_back = self.knockback_timer > 0.0;
+        if !knocked_back {
+            self.vel.x = dir * move_speed;
+            if dir != 0.0 {
+                self.facing_right = dir > 0.0;
+            }
+        }
+        let jump_speed = if self.high_jump_timer > 0.0 {
+            HIGH_JUMP_SPEED
+        } else {
+            JUMP_SPEED
+        }; // NEW
+        if self.on_ground && input.jump && !knocked_back {
+            self.vel.y = -jump_speed;
+            self.on_ground = false;
+        }
+        if self.speed_timer > 0.0 {
+            self.speed_timer -= dt / TIMER_BOOST;
+        }
+        if self.invincible_timer > 0.0 {
+            self.invincible_timer -= dt / TIMER_BOOST;
+        }
+        if self.high_jump_timer > 0.0 {
+            self.high_jump_timer -= dt / TIMER_BOOST;
+        }
+        self.hurt_timer = (self.hurt_timer - dt).max(0.0);
+        self.invulnerable_timer = (self.invulnerable_timer - dt).max(0.0);
+        self.knockback_timer = (self.knockback_timer - dt).max(0.0);
+        self.shoot_timer = (self.shoot_timer - dt).max(0.0);
+        for weapon in &mut self.weapons {
+            weapon.update(dt);
+        }
+
+        self.vel.y += GRAVITY * dt;
+        let size = vec2(PLAYER_WIDTH, PLAYER_HEIGHT);
+        let contacts = physics::move_and_collide(&mut self.pos, &mut self.vel, size, dt, solids);
+        self.on_ground = contacts.on_ground;
+        if self.on_ground {
+            self.knockback_timer = 0.0;
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.x, self.pos.y, PLAYER_WIDTH, PLAYER_HEIGHT)
+    }
+
+    /// Whether enemies and hazards can't hurt the player right now, from a
+    /// power-up or a recent hit. Things that kill still do.
+    pub fn is_invulnerable(&self) -> bool {
+        self.invincible_timer > 0.0 || self.invulnerable_timer > 0.0
+    }
+
+    /// Throws the player up and away from `from` (horizontally).
+    pub fn knock_back(&mut self, from: Vec2) {
+        let center = self.rect().center();
+        let dir = if center.x < from.x { -1.0 } else { 1.0 };
+        self.vel = vec2(dir * KNOCKBACK_SPEED, -KNOCKBACK_LIFT);
+        self.on_ground = false;
+        self.knockback_timer = KNOCKBACK_TIME;
+    }
+
+    /// The weapon in hand.
+    pub fn weapon(&self) -> &Weapon {
+        &self.weapons[self.weapon]
+    }
+
+    pub fn next_weapon(&mut self) {
+        self.weapon = (self.weapon + 1) % self.weapons.len();
+    }
+
+    /// Takes a picked-up weapon in hand, or adds its ammo to the one of
+    /// that kind the player already has.
+    pub fn pick_up(&mut self, kind: WeaponKind) {
+        match self.weapons.iter().position(|weapon| weapon.kind == kind) {
+            Some(i) => {
+                let stats = kind.stats();
+                self.weapons[i].spare += stats.magazine.unwrap_or(0) + stats.ammo;
+                self.weapon = i;
+            }
+            None => {
+                self.weapons.push(Weapon::new(kind));
+                self.weapon = self.weapons.len() - 1;
+            }
+        }
+    }
+
+    /// Starts reloading the weapon in hand early, if it can be.
+    pub fn reload(&mut self) {
+        self.weapons[self.weapon].reload();
+    }
+
+    /// Uses up a shot of the weapon in hand. Once it has no ammo left at
+    /// all it's dropped, and the pistol taken in hand instead.
+    pub fn fire_weapon(&mut self) {
+        let weapon = &mut self.weapons[self.weapon];
+        weapon.fire();
+        if weapon.is_spent() {
+            self.weapons.remove(self.weapon);
+            self.weapon = 0;
+        }
+    }
+
+    pub fn reset(&mut self, pos: Vec2) {
+        self.pos = pos;
+        self.vel = Vec2::ZERO;
+        self.prev_pos = pos;
+        self.knockback_timer = 0.0;
+    }
+}
@@ -0,0 +1,843 @@
+/*
+ Game flow. A Game owns the World and everything around a session (saves,
+ high scores, replays) and moves between GameStates: the title menu,
+ playing, the pause menu, the short "level complete" break, and the game
+ over and victory screens, and the level editor. Changing state goes
+ through set_state(), which runs the exit hook of the old state and the
+ enter hook of the new one.
+*/
+
+use std::path::PathBuf;
+
+use macroquad::prelude::*;
+
+use crate::animation::{AnimEvent, Animators};
+use crate::archetype::Archetypes;
+use crate::camera::Camera;
+use crate::editor::{Command, Editor};
+use crate::input::{Action, Controls};
+use crate::render::{self, Effects};
+use crate::replay::{Playback, Replay};
+use crate::save::SaveGame;
+use crate::scores::{HighScores, MAX_NAME_LEN};
+use crate::sprites::Sprites;
+use crate::world::{Difficulty, InputFrame, LevelResult, World};
+use crate::{GAME_SPEED, MAX_FRAME_TIME, MAX_HEALTH, PLAYER_HEIGHT, PLAYER_WIDTH, STEP_DT};
+
+/// How long the "level complete" break lasts, in seconds.
+const LEVEL_COMPLETE_TIME: f32 = 2.5;
+
+pub enum GameState {
+    Title {
+        selected: usize,
+    },
+    Playing,
+    Paused {
+        selected: usize,
+    },
+    /// The break after finishing a level; the world is already on the next.
+    LevelComplete {
+        result: LevelResult,
+        time_left: f32,
+    },
+    /// `name_entry` is the name being typed for a new high score, if any.
+    GameOver {
+        name_entry: Option<String>,
+    },
+    Victory {
+        name_entry: Option<String>,
+    },
+    /// The level editor is open; see `Game::editor`.
+    Editing,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum MenuItem {
+    Continue,
+    NewGame,
+    /// Shows the difficulty the next run is played on; choosing it changes
+    /// it.
+    Difficulty(Difficulty),
+    Resume,
+    RestartLevel,
+    QuitToTitle,
+    Quit,
+}
+
+impl MenuItem {
+    fn label(self) -> &'static str {
+        match self {
+            MenuItem::Continue => "Continue",
+            MenuItem::NewGame => "New Game",
+            MenuItem::Difficulty(Difficulty::Easy) => "Difficulty: Easy",
+            MenuItem::Difficulty(Difficulty::Normal) => "Difficulty: Normal",
+            MenuItem::Difficulty(Difficulty::Hard) => "Difficulty: Hard",
+            MenuItem::Resume => "Resume",
+            MenuItem::RestartLevel => "Restart Level",
+            MenuItem::QuitToTitle => "Quit to Title",
+            MenuItem::Quit => "Quit",
+        }
+    }
+}
+
+const PAUSE_MENU: [MenuItem; 3] = [
+    MenuItem::Resume,
+    MenuItem::RestartLevel,
+    MenuItem::QuitToTitle,
+];
+
+pub struct Game {
+    world: World,
+    controls: Controls,
+    sprites: Sprites,
+    /// For the editor to place enemies with.
+    archetypes: Archetypes,
+    animators: Animators,
+    effects: Effects,
+    camera: Camera,
+    state: GameState,
+    /// Set when the player has chosen to leave the game.
+    pub quit: bool,
+    accumulator: f32,
+    input: InputFrame,
+    save_file: Option<PathBuf>,
+    /// `None` until there's been something to save.
+    save_game: Option<SaveGame>,
+    scores_file: Option<PathBuf>,
+    high_scores: HighScores,
+    record_file: PathBuf,
+    recording: Option<Replay>,
+    playback: Option<Playback>,
+    /// Whether the replay ended where it was recorded to, once it's over.
+    replay_result: Option<bool>,
+    /// The file each of the world's levels was loaded from.
+    level_files: Vec<PathBuf>,
+    /// The level editor, once opened. Playing while there is one is
+    /// play-testing its level.
+    editor: Option<Editor>,
+}
+
+impl Game {
+    /// A game at the title screen, or playing `playback` straight away if
+    /// there is one. Progress and high scores are only loaded and saved
+    /// when not watching a replay.
+    pub fn new(
+        mut world: World,
+        controls: Controls,
+        sprites: Sprites,
+        archetypes: Archetypes,
+        playback: Option<Playback>,
+        record_file: PathBuf,
+        level_files: Vec<PathBuf>,
+    ) -> Self {
+        let watching = playback.is_some();
+        let save_file = SaveGame::path().filter(|_| !watching);
+        let save_game = match save_file.as_deref().map(SaveGame::load) {
+            Some(Ok(saved)) => saved,
+            Some(Err(err)) => {
+                eprintln!("ignoring the save file: {}", err);
+                None
+            }
+            None => None,
+        };
+        let scores_file = HighScores::path().filter(|_| !watching);
+        let high_scores = match scores_file.as_deref().map(HighScores::load) {
+            Some(Ok(scores)) => scores,
+            Some(Err(err)) => {
+                eprintln!("ignoring the high scores: {}", err);
+                HighScores::default()
+            }
+            None => HighScores::default(),
+        };
+
+        if let Some(saved) = &save_game {
+            world.difficulty = saved.difficulty;
+        }
+        let mut game = Game {
+            world,
+            controls,
+            sprites,
+            archetypes,
+            animators: Animators::default(),
+            effects: Effects::default(),
+            camera: Camera::default(),
+            state: GameState::Title { selected: 0 },
+            quit: false,
+            accumulator: 0.0,
+            input: InputFrame::default(),
+            save_file,
+            save_game,
+            scores_file,
+            high_scores,
+            record_file,
+            recording: None,
+            playback,
+            replay_result: None,
+            level_files,
+            editor: None,
+        };
+        if watching {
+            game.set_state(GameState::Playing);
+        }
+        game
+    }
+
+    /// Leaves the current state for `next`, running both hooks.
+    fn set_state(&mut self, next: GameState) {
+        self.exit_state();
+        self.state = next;
+        self.enter_state();
+    }
+
+    fn exit_state(&mut self) {
+        match self.state {
+            // Presses that no step has seen yet shouldn't fire on return
+            GameState::Playing => self.input = InputFrame::default(),
+            // Edits are played with from now on, saved or not
+            GameState::Editing => {
+                if let Some(editor) = &self.editor {
+                    self.world.levels[editor.index] = editor.level.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn enter_state(&mut self) {
+        if matches!(
+            self.state,
+            GameState::LevelComplete { .. } | GameState::Victory { .. }
+        ) {
+            self.save_progress();
+        }
+        match &mut self.state {
+            GameState::Title { .. } => {
+                self.end_recording();
+                self.close_editor();
+            }
+            GameState::Playing => self.accumulator = 0.0,
+            GameState::LevelComplete { .. } => {
+                self.animators.reset_enemies();
+                self.camera.reset();
+            }
+            GameState::GameOver { name_entry } | GameState::Victory { name_entry } => {
+                let score = self.world.player.score;
+                if self.scores_file.is_some() && self.high_scores.qualifies(score) {
+                    // Drop whatever was typed while playing
+                    while get_char_pressed().is_some() {}
+                    *name_entry = Some(String::new());
+                }
+            }
+            GameState::Paused { .. } | GameState::Editing => {}
+        }
+    }
+
+    /// Writes the progress made on finishing a level (or the last one) to
+    /// the save file.
+    fn save_progress(&mut self) {
+        if let Some(file) = &self.save_file {
+            let save_game = self.save_game.get_or_insert_with(SaveGame::default);
+            save_game.update(&self.world);
+            if let Err(err) = save_game.save(file) {
+                eprintln!("error saving the game: {}", err);
+            }
+        }
+    }
+
+    /// Handles one frame of input and simulation, then draws it.
+    pub fn frame(&mut self) {
+        self.controls.update();
+        if is_quit_requested() {
+            self.end_recording();
+            self.quit = true;
+            return;
+        }
+
+        match self.state {
+            GameState::Title { .. } => self.update_title(),
+            GameState::Playing => self.update_playing(),
+            GameState::Paused { .. } => self.update_paused(),
+            GameState::LevelComplete { .. } => self.update_level_complete(),
+            GameState::GameOver { .. } | GameState::Victory { .. } => self.update_run_over(),
+            GameState::Editing => self.update_editing(),
+        }
+        if self.quit {
+            return;
+        }
+        match self.state {
+            GameState::Title { .. } | GameState::Victory { .. } | GameState::Editing => {}
+            GameState::Playing => {
+                let dt = get_frame_time();
+                self.animate(dt);
+                self.update_camera(dt);
+            }
+            // Frozen, but still catching up with any cut to a new spot
+            _ => self.update_camera(0.0),
+        }
+        self.draw();
+    }
+
+    fn title_menu(&self) -> Vec<MenuItem> {
+        let mut items = Vec::new();
+        if self.save_game.is_some() {
+            items.push(MenuItem::Continue);
+        }
+        items.extend([
+            MenuItem::NewGame,
+            MenuItem::Difficulty(self.world.difficulty),
+            MenuItem::Quit,
+        ]);
+        items
+    }
+
+    fn update_title(&mut self) {
+        if self.controls.pressed(Action::Quit) {
+            self.quit = true;
+            return;
+        }
+        let items = self.title_menu();
+        let GameState::Title { selected } = &mut self.state else {
+            return;
+        };
+        match choose(&self.controls, selected, &items) {
+            Some(MenuItem::Continue) => {
+                let save_game = self.save_game.clone().unwrap_or_default();
+                self.start_run(|world| {
+                    if let Err(err) = save_game.restore(world) {
+                        eprintln!("ignoring the save file: {}", err);
+                        world.restart();
+                    }
+                });
+            }
+            Some(MenuItem::NewGame) => self.start_run(World::restart),
+            Some(MenuItem::Difficulty(difficulty)) => self.world.difficulty = difficulty.next(),
+            Some(MenuItem::Quit) => self.quit = true,
+            _ => {}
+        }
+    }
+
+    fn update_playing(&mut self) {
+        if self.controls.pressed(Action::Edit) && self.playback.is_none() {
+            self.open_editor();
+            return;
+        }
+        if self.controls.pressed(Action::Pause) {
+            self.set_state(GameState::Paused { selected: 0 });
+            return;
+        }
+        if self.controls.pressed(Action::Quit) {
+            self.quit_to_title();
+            return;
+        }
+
+        self.accumulator += (get_frame_time() * GAME_SPEED).min(MAX_FRAME_TIME);
+        let step_dt = self.step_dt();
+
+        // Presses are kept until a step has seen them, even on frames too
+        // short to run one.
+        let frame_input = self.controls.frame();
+        self.input = InputFrame {
+            jump: self.input.jump || frame_input.jump,
+            shoot: self.input.shoot || frame_input.shoot,
+            switch_weapon: self.input.switch_weapon || frame_input.switch_weapon,
+            reload: self.input.reload || frame_input.reload,
+            ..frame_input
+        };
+        while self.accumulator >= step_dt {
+            let step_input = match &mut self.playback {
+                Some(playback) => match playback.next_input() {
+                    Some(input) => input,
+                    None => {
+                        self.accumulator = 0.0;
+                        break;
+                    }
+                },
+                None => self.input,
+            };
+            self.world.step(step_input, step_dt);
+            for &pos in &self.world.impacts {
+                self.effects.spawn(&AnimEvent {
+                    name: "impact".to_string(),
+                    pos,
+                });
+            }
+            if let Some(recording) = &mut self.recording {
+                recording.record(step_input, &self.world);
+            }
+            self.input = self.input.held();
+            self.accumulator -= step_dt;
+            // Anything after this waits until the next state is over
+            if self.world.last_completed.is_some() || !self.world.player.alive {
+                break;
+            }
+        }
+
+        if let Some(playback) = &self.playback
+            && playback.finished()
+            && self.replay_result.is_none()
+        {
+            let matches = playback.matches(&self.world);
+            println!("replay {}", if matches { "matches" } else { "diverged" });
+            self.replay_result = Some(matches);
+        }
+
+        // A play-test goes back to the editor however it ends
+        if self.editor.is_some()
+            && (self.world.last_completed.take().is_some() || !self.world.player.alive)
+        {
+            self.set_state(GameState::Editing);
+            return;
+        }
+
+        if let Some(result) = self.world.last_completed.take() {
+            if self.scores_file.is_some() {
+                self.high_scores.record_level(result);
+                self.save_scores();
+            }
+            if self.world.won {
+                self.set_state(GameState::Victory { name_entry: None });
+            } else {
+                self.set_state(GameState::LevelComplete {
+                    result,
+                    time_left: LEVEL_COMPLETE_TIME,
+                });
+            }
+        } else if !self.world.player.alive {
+            self.set_state(GameState::GameOver { name_entry: None });
+        }
+    }
+
+    fn update_paused(&mut self) {
+        if self.controls.pressed(Action::Pause) {
+            self.set_state(GameState::Playing);
+            return;
+        }
+        if self.controls.pressed(Action::Edit) && self.playback.is_none() {
+            self.open_editor();
+            return;
+        }
+        let GameState::Paused { selected } = &mut self.state else {
+            return;
+        };
+        match choose(&self.controls, selected, &PAUSE_MENU) {
+            Some(MenuItem::Resume) => self.set_state(GameState::Playing),
+            Some(MenuItem::RestartLevel) => self.start_run(World::restart_level),
+            Some(MenuItem::QuitToTitle) => self.quit_to_title(),
+            _ => {}
+        }
+    }
+
+    fn update_level_complete(&mut self) {
+        let GameState::LevelComplete { time_left, .. } = &mut self.state else {
+            return;
+        };
+        *time_left -= get_frame_time();
+        if *time_left <= 0.0 || self.controls.pressed(Action::Confirm) {
+            self.set_state(GameState::Playing);
+        }
+    }
+
+    /// Game over and victory: type a name if the score made the table,
+    /// then restart or leave.
+    fn update_run_over(&mut self) {
+        let (GameState::GameOver { name_entry } | GameState::Victory { name_entry }) =
+            &mut self.state
+        else {
+            return;
+        };
+        if let Some(name) = name_entry {
+            while let Some(c) = get_char_pressed() {
+                if !c.is_control() && name.chars().count() < MAX_NAME_LEN {
+                    name.push(c);
+                }
+            }
+            if is_key_pressed(KeyCode::Backspace) {
+                name.pop();
+            }
+            if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+                let name = match name.trim() {
+                    "" => "Player",
+                    name => name,
+                };
+                self.high_scores.insert(name, self.world.player.score);
+                *name_entry = None;
+                self.save_scores();
+            }
+            return;
+        }
+
+        if self.controls.pressed(Action::Restart) {
+            if let GameState::Victory { .. } = self.state {
+                self.start_run(World::restart);
+            } else {
+                self.start_run(World::restart_level);
+            }
+        } else if self.controls.pressed(Action::Quit) {
+            self.quit_to_title();
+        }
+    }
+
+    fn update_editing(&mut self) {
+        if self.controls.pressed(Action::Edit) {
+            self.play_test(None);
+            return;
+        }
+        if self.controls.pressed(Action::Quit) {
+            let keys = self.controls.key_names(Action::Quit);
+            if self
+                .editor
+                .as_mut()
+                .is_none_or(|editor| editor.may_quit(&keys))
+            {
+                self.quit_to_title();
+            }
+            return;
+        }
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        if let Some(Command::PlayTest(from)) = editor.update() {
+            self.play_test(from);
+        }
+    }
+
+    /// Opens the editor on the current level. Coming back from a
+    /// play-test, it's the same editor as before, undo history and all.
+    /// The run that was being played is over, and play-tests aren't
+    /// recorded: they'd never replay the same from the level files.
+    fn open_editor(&mut self) {
+        self.end_recording();
+        let index = self.world.current_level;
+        if self.editor.as_ref().is_none_or(|e| e.index != index) {
+            self.close_editor();
+            self.editor = Some(Editor::new(
+                index,
+                self.level_files.get(index).cloned(),
+                self.world.level().clone(),
+                self.camera.pos,
+                self.archetypes.clone(),
+            ));
+        }
+        self.set_state(GameState::Editing);
+    }
+
+    /// Drops the editor, putting its level back the way it's saved: runs
+    /// only ever play the level files, like their replays do.
+    fn close_editor(&mut self) {
+        if let Some(editor) = self.editor.take() {
+            self.world.levels[editor.index] = editor.saved;
+        }
+    }
+
+    /// Plays the level being edited from its start, or with the player's
+    /// feet at `from`.
+    fn play_test(&mut self, from: Option<Vec2>) {
+        let Some(index) = self.editor.as_ref().map(|e| e.index) else {
+            return;
+        };
+        // Leaving the editor hands its level to the world
+        self.set_state(GameState::Playing);
+        self.world.start_run(index);
+        if let Some(feet) = from {
+            let pos = feet - vec2(PLAYER_WIDTH / 2.0, PLAYER_HEIGHT);
+            self.world.player.reset(pos);
+        }
+        self.animators.reset_enemies();
+        self.effects.clear();
+        self.camera.reset();
+    }
+
+    /// Moves every animation and effect on by `dt` seconds.
+    fn animate(&mut self, dt: f32) {
+        let mut events = Vec::new();
+        self.animators
+            .update(&self.world, &self.sprites, dt, &mut events);
+        for event in &events {
+            self.effects.spawn(event);
+        }
+        self.effects.update(dt);
+    }
+
+    fn update_camera(&mut self, dt: f32) {
+        let player = &self.world.player;
+        let pos = player.prev_pos.lerp(player.pos, self.alpha());
+        let target = pos + vec2(PLAYER_WIDTH, PLAYER_HEIGHT) / 2.0;
+        let level = self.world.level();
+        let view = vec2(screen_width(), screen_height());
+        let bounds = level.bounds();
+        self.camera
+            .update(&level.camera, target, player.facing_right, view, bounds, dt);
+    }
+
+    /// Begins a run, set up by `how`. The recording of the previous run is
+    /// saved and a new one begun; a replay starts over from its first step
+    /// instead.
+    fn start_run(&mut self, how: impl FnOnce(&mut World)) {
+        self.end_recording();
+        self.animators.reset_enemies();
+        self.effects.clear();
+        self.camera.reset();
+        if let Some(playback) = &mut self.playback {
+            // The replay loaded fine once, so it can't fail now
+            let _ = playback.rewind(&mut self.world);
+            self.replay_result = None;
+        } else {
+            how(&mut self.world);
+            // Play-tests aren't recorded (see open_editor)
+            if self.editor.is_none() {
+                self.recording = Some(Replay::start(&self.world, STEP_DT));
+            }
+        }
+        self.set_state(GameState::Playing);
+    }
+
+    /// Back to the title screen, or out of the game when watching a replay.
+    /// A play-test goes back to the editor instead, so the edits aren't
+    /// dropped unasked.
+    fn quit_to_title(&mut self) {
+        if self.playback.is_some() {
+            self.quit = true;
+        } else if self.editor.is_some() && !matches!(self.state, GameState::Editing) {
+            self.open_editor();
+        } else {
+            self.set_state(GameState::Title { selected: 0 });
+        }
+    }
+
+    fn end_recording(&mut self) {
+        if let Some(recording) = self.recording.take()
+            && let Err(err) = recording.save(&self.record_file)
+        {
+            eprintln!("error saving replay: {}", err);
+        }
+    }
+
+    fn save_scores(&self) {
+        if let Some(file) = &self.scores_file
+            && let Err(err) = self.high_scores.save(file)
+        {
+            eprintln!("error saving high scores: {}", err);
+        }
+    }
+
+    /// How far we are between the last step and the next one.
+    fn alpha(&self) -> f32 {
+        self.accumulator / self.step_dt()
+    }
+
+    fn step_dt(&self) -> f32 {
+        self.playback.as_ref().map_or(STEP_DT, |p| p.replay.step_dt)
+    }
+
+    fn draw(&self) {
+        clear_background(LIGHTGRAY);
+        let center_x = screen_width() / 2.0;
+        let center_y = screen_height() / 2.0;
+        let restart_help = format!(
+            "Press {} to Restart",
+            self.controls.key_names(Action::Restart)
+        );
+
+        match &self.state {
+            GameState::Editing => {
+                if let Some(editor) = &self.editor {
+                    editor.draw(&self.sprites, &self.controls);
+                }
+                return;
+            }
+            GameState::Title { selected } => {
+                draw_text(
+                    "ADVENTURE",
+                    center_x - 200.0,
+                    center_y - 80.0,
+                    56.0,
+                    DARKGREEN,
+                );
+                draw_menu(&self.title_menu(), *selected, center_x - 200.0, center_y);
+                draw_high_scores(&self.high_scores, None);
+                return;
+            }
+            GameState::Victory { name_entry } => {
+                draw_text("YOU WIN!", center_x - 90.0, center_y, 56.0, DARKGREEN);
+                draw_text(
+                    &format!("Final Score: {}", self.world.player.score),
+                    center_x - 120.0,
+                    center_y + 60.0,
+                    40.0,
+                    BLACK,
+                );
+                draw_text(
+                    &run_over_help(name_entry, &restart_help),
+                    center_x - 110.0,
+                    center_y + 120.0,
+                    32.0,
+                    BLACK,
+                );
+                draw_high_scores(&self.high_scores, name_entry.as_deref());
+                return;
+            }
+            _ => {}
+        }
+
+        render::draw_world(
+            &self.world,
+            &self.sprites,
+            &self.animators,
+            &self.effects,
+            self.camera.pos,
+            self.alpha(),
+        );
+        self.draw_hud();
+
+        match &self.state {
+            GameState::Paused { selected } => {
+                draw_text("PAUSED", center_x - 75.0, center_y - 60.0, 48.0, DARKBLUE);
+                draw_menu(&PAUSE_MENU, *selected, center_x - 75.0, center_y);
+            }
+            GameState::LevelComplete { result, .. } => {
+                draw_text(
+                    &format!("LEVEL {} COMPLETE", result.level + 1),
+                    center_x - 170.0,
+                    center_y - 40.0,
+                    48.0,
+                    DARKGREEN,
+                );
+                draw_text(
+                    &format!("+{} points in {:.2} s", result.score, result.time),
+                    center_x - 130.0,
+                    center_y + 10.0,
+                    32.0,
+                    BLACK,
+                );
+            }
+            GameState::GameOver { name_entry } => {
+                draw_text("GAME OVER", center_x - 100.0, center_y, 48.0, RED);
+                draw_text(
+                    &run_over_help(name_entry, &restart_help),
+                    center_x - 110.0,
+                    center_y + 50.0,
+                    32.0,
+                    BLACK,
+                );
+                draw_high_scores(&self.high_scores, name_entry.as_deref());
+            }
+            _ => {}
+        }
+    }
+
+    fn draw_hud(&self) {
+        let player = &self.world.player;
+        let health_str = format!("Health: {}/{}", player.health, MAX_HEALTH);
+        draw_text(&health_str, 10.0, 30.0, 30.0, RED);
+        let score_str = format!("Score: {}", player.score);
+        draw_text(&score_str, 10.0, 65.0, 30.0, BLACK);
+        let weapon = player.weapon();
+        let ammo = if weapon.is_reloading() {
+            " (reloading)".to_string()
+        } else if weapon.kind.stats().magazine.is_some() {
+            format!(" {} + {}", weapon.loaded, weapon.spare)
+        } else {
+            String::new()
+        };
+        let weapon_str = format!("{}{}", weapon.kind.name(), ammo);
+        draw_text(&weapon_str, 220.0, 65.0, 30.0, DARKGRAY);
+        // Recomputed every frame so they follow gamepads being plugged in
+        for (i, line) in self.controls.help_lines().iter().enumerate() {
+            draw_text(line, 10.0, 100.0 + i as f32 * 22.0, 24.0, BLACK);
+        }
+
+        if player.speed_timer > 0.0 {
+            draw_text("SPEED!", 10.0, 152.0, 28.0, ORANGE);
+        }
+        if player.invincible_timer > 0.0 {
+            draw_text("INVINCIBLE!", 10.0, 182.0, 28.0, YELLOW);
+        }
+        if player.high_jump_timer > 0.0 {
+            draw_text("HIGH JUMP!", 10.0, 212.0, 28.0, BLUE);
+        }
+
+        if let Some(matches) = self.replay_result {
+            let (text, color) = if matches {
+                ("REPLAY FINISHED: MATCHES", DARKGREEN)
+            } else {
+                ("REPLAY FINISHED: DIVERGED", RED)
+            };
+            draw_text(text, screen_width() / 2.0 - 180.0, 60.0, 32.0, color);
+        } else if self.playback.is_some() {
+            draw_text("REPLAY", screen_width() - 120.0, 50.0, 32.0, DARKBLUE);
+        } else if self.editor.is_some() {
+            let text = format!(
+                "PLAY-TEST ({} = back to editor)",
+                self.controls.key_names(Action::Edit)
+            );
+            draw_text(&text, screen_width() - 360.0, 50.0, 28.0, DARKBLUE);
+        }
+    }
+}
+
+/// Moves the menu selection with AimUp/AimDown and returns the item picked
+/// with Confirm, if any.
+fn choose(controls: &Controls, selected: &mut usize, items: &[MenuItem]) -> Option<MenuItem> {
+    if controls.pressed(Action::AimUp) {
+        *selected = (*selected + items.len() - 1) % items.len();
+    }
+    if controls.pressed(Action::AimDown) {
+        *selected = (*selected + 1) % items.len();
+    }
+    controls.pressed(Action::Confirm).then(|| items[*selected])
+}
+
+fn draw_menu(items: &[MenuItem], selected: usize, x: f32, y: f32) {
+    for (i, item) in items.iter().enumerate() {
+        let (prefix, color) = if i == selected {
+            ("> ", DARKBLUE)
+        } else {
+            ("  ", BLACK)
+        };
+        let text = format!("{}{}", prefix, item.label());
+        draw_text(&text, x, y + i as f32 * 36.0, 32.0, color);
+    }
+}
+
+fn run_over_help(name_entry: &Option<String>, restart_help: &str) -> String {
+    match name_entry {
+        Some(_) => "NEW HIGH SCORE! Type your name, Enter = OK".to_string(),
+        None => restart_help.to_string(),
+    }
+}
+
+/// The high score table and per-level records, in a panel at the top right.
+/// `name_entry` is the name being typed for a new record, if any.
+fn draw_high_scores(scores: &HighScores, name_entry: Option<&str>) {
+    let x = screen_width() - 280.0;
+    let mut y = 30.0;
+    let rows = scores.table.len() + scores.levels.len() + 4;
+    draw_rectangle(
+        x - 10.0,
+        5.0,
+        280.0,
+        rows as f32 * 22.0 + 20.0,
+        Color::new(1.0, 1.0, 1.0, 0.8),
+    );
+    let mut line = |text: &str, color: Color| {
+        draw_text(text, x, y, 24.0, color);
+        y += 22.0;
+    };
+    if let Some(name) = name_entry {
+        line(&format!("Name: {}_", name), RED);
+    }
+    line("HIGH SCORES", DARKBLUE);
+    for (i, entry) in scores.table.iter().enumerate() {
+        line(
+            &format!("{:>2}. {:<12} {:>6}", i + 1, entry.name, entry.score),
+            BLACK,
+        );
+    }
+    line("LEVEL BESTS", DARKBLUE);
+    for (i, best) in scores.levels.iter().enumerate() {
+        if let Some(best) = best {
+            let text = format!("Level {}: {} pts, {:.2} s", i + 1, best.score, best.time);
+            line(&text, BLACK);
+        }
+    }
+}
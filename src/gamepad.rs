@@ -0,0 +1,210 @@
+/*
+ Gamepad state, read through gilrs when the `gamepad` feature is on
+ (cargo run --features gamepad; needs libudev on Linux). Without the
+ feature Gamepads is a stand-in that never sees a controller, so the rest of
+ the game doesn't have to care.
+*/
+
+use macroquad::math::Vec2;
+
+/// A controller button, named after its position (South is A on an Xbox
+/// pad, Cross on a PlayStation one).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl PadButton {
+    pub const ALL: [PadButton; 16] = [
+        PadButton::South,
+        PadButton::East,
+        PadButton::North,
+        PadButton::West,
+        PadButton::LeftTrigger,
+        PadButton::LeftTrigger2,
+        PadButton::RightTrigger,
+        PadButton::RightTrigger2,
+        PadButton::Select,
+        PadButton::Start,
+        PadButton::LeftThumb,
+        PadButton::RightThumb,
+        PadButton::DPadUp,
+        PadButton::DPadDown,
+        PadButton::DPadLeft,
+        PadButton::DPadRight,
+    ];
+}
+
+/// Snaps a stick position to one of 8 directions, with x right and y up, or
+/// returns `None` inside the dead zone.
+pub fn eight_way(stick: Vec2, deadzone: f32) -> Option<(i32, i32)> {
+    if stick.length() < deadzone {
+        return None;
+    }
+    let sector = (stick.y.atan2(stick.x) / std::f32::consts::FRAC_PI_4).round() as i32;
+    let dirs = [
+        (1, 0),
+        (1, 1),
+        (0, 1),
+        (-1, 1),
+        (-1, 0),
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+    ];
+    Some(dirs[sector.rem_euclid(8) as usize])
+}
+
+/// Whether this build can read controllers at all.
+pub const SUPPORTED: bool = cfg!(feature = "gamepad");
+
+#[cfg(feature = "gamepad")]
+pub use self::gilrs_pads::Gamepads;
+
+#[cfg(not(feature = "gamepad"))]
+pub use self::no_pads::Gamepads;
+
+#[cfg(feature = "gamepad")]
+mod gilrs_pads {
+    use std::collections::HashSet;
+
+    use gilrs::{Axis, Button, Gilrs};
+    use macroquad::math::vec2;
+
+    use super::*;
+
+    /// Every connected controller merged into one: a button is down if it's
+    /// down on any pad, and the stick is whichever is pushed furthest.
+    pub struct Gamepads {
+        gilrs: Option<Gilrs>,
+        held: HashSet<PadButton>,
+        prev_held: HashSet<PadButton>,
+        stick: Vec2,
+    }
+
+    impl Default for Gamepads {
+        fn default() -> Self {
+            let gilrs = match Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(err) => {
+                    eprintln!("gamepads unavailable: {}", err);
+                    None
+                }
+            };
+            Gamepads {
+                gilrs,
+                held: HashSet::new(),
+                prev_held: HashSet::new(),
+                stick: Vec2::ZERO,
+            }
+        }
+    }
+
+    impl Gamepads {
+        /// Reads the controllers; call once per frame.
+        pub fn update(&mut self) {
+            self.prev_held = std::mem::take(&mut self.held);
+            self.stick = Vec2::ZERO;
+            let Some(gilrs) = &mut self.gilrs else {
+                return;
+            };
+            // gilrs only updates its gamepad state as events are read
+            while gilrs.next_event().is_some() {}
+
+            for (_, pad) in gilrs.gamepads() {
+                for button in PadButton::ALL {
+                    if pad.is_pressed(to_gilrs(button)) {
+                        self.held.insert(button);
+                    }
+                }
+                let stick = vec2(pad.value(Axis::LeftStickX), pad.value(Axis::LeftStickY));
+                if stick.length() > self.stick.length() {
+                    self.stick = stick;
+                }
+            }
+        }
+
+        pub fn connected(&self) -> bool {
+            self.gilrs
+                .as_ref()
+                .is_some_and(|gilrs| gilrs.gamepads().next().is_some())
+        }
+
+        pub fn down(&self, button: PadButton) -> bool {
+            self.held.contains(&button)
+        }
+
+        pub fn pressed(&self, button: PadButton) -> bool {
+            self.held.contains(&button) && !self.prev_held.contains(&button)
+        }
+
+        /// The left stick, x right and y up, each -1.0..=1.0.
+        pub fn stick(&self) -> Vec2 {
+            self.stick
+        }
+    }
+
+    fn to_gilrs(button: PadButton) -> Button {
+        match button {
+            PadButton::South => Button::South,
+            PadButton::East => Button::East,
+            PadButton::North => Button::North,
+            PadButton::West => Button::West,
+            PadButton::LeftTrigger => Button::LeftTrigger,
+            PadButton::LeftTrigger2 => Button::LeftTrigger2,
+            PadButton::RightTrigger => Button::RightTrigger,
+            PadButton::RightTrigger2 => Button::RightTrigger2,
+            PadButton::Select => Button::Select,
+            PadButton::Start => Button::Start,
+            PadButton::LeftThumb => Button::LeftThumb,
+            PadButton::RightThumb => Button::RightThumb,
+            PadButton::DPadUp => Button::DPadUp,
+            PadButton::DPadDown => Button::DPadDown,
+            PadButton::DPadLeft => Button::DPadLeft,
+            PadButton::DPadRight => Button::DPadRight,
+        }
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+mod no_pads {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Gamepads {}
+
+    impl Gamepads {
+        pub fn update(&mut self) {}
+
+        pub fn connected(&self) -> bool {
+            false
+        }
+
+        pub fn down(&self, _button: PadButton) -> bool {
+            false
+        }
+
+        pub fn pressed(&self, _button: PadButton) -> bool {
+            false
+        }
+
+        pub fn stick(&self) -> Vec2 {
+            Vec2::ZERO
+        }
+    }
+}
@@ -0,0 +1,451 @@
+/*
+ Key and gamepad bindings. Every control is an Action bound to one or more
+ keys or gamepad buttons. The bindings are read from controls.json, e.g.
+
+ {
+     "bindings": {
+         "Jump": ["LeftAlt", "RightAlt", "K", "PadSouth"],
+         "Shoot": ["LeftControl", "J", "PadWest"]
+     },
+     "stick_deadzone": 0.3
+ }
+
+ Actions the file leaves out keep their default bindings. Key names are
+ macroquad's KeyCode names (A, Key1, Space, LeftShift, Up, F1, Kp0, ...);
+ gamepad buttons are "Pad" followed by a PadButton name (PadSouth,
+ PadStart, PadDPadUp, ...). The left stick always moves and aims, snapped
+ to 8 directions, once it's pushed past the dead zone.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Deserializer};
+
+use crate::data::{self, DataError};
+use crate::gamepad::{self, Gamepads, PadButton};
+use crate::world::InputFrame;
+
+pub const CONTROLS_FILE: &str = "controls.json";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Run,
+    Shoot,
+    SwitchWeapon,
+    Reload,
+    AimUp,
+    AimDown,
+    Pause,
+    Restart,
+    Quit,
+    Confirm,
+    Edit,
+}
+
+impl Action {
+    pub const ALL: [Action; 14] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Run,
+        Action::Shoot,
+        Action::SwitchWeapon,
+        Action::Reload,
+        Action::AimUp,
+        Action::AimDown,
+        Action::Pause,
+        Action::Restart,
+        Action::Quit,
+        Action::Confirm,
+        Action::Edit,
+    ];
+
+    /// How the action is called in the on-screen help.
+    fn label(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "left",
+            Action::MoveRight => "right",
+            Action::Jump => "jump",
+            Action::Run => "run",
+            Action::Shoot => "shoot",
+            Action::SwitchWeapon => "switch weapon",
+            Action::Reload => "reload",
+            Action::AimUp => "aim up",
+            Action::AimDown => "aim down",
+            Action::Pause => "pause",
+            Action::Restart => "restart",
+            Action::Quit => "quit",
+            Action::Confirm => "select",
+            Action::Edit => "edit level",
+        }
+    }
+
+    fn default_bindings(self) -> Vec<Binding> {
+        use Binding::{Key, Pad};
+        match self {
+            Action::MoveLeft => vec![
+                Key(KeyCode::Left),
+                Key(KeyCode::A),
+                Pad(PadButton::DPadLeft),
+            ],
+            Action::MoveRight => vec![
+                Key(KeyCode::Right),
+                Key(KeyCode::D),
+                Pad(PadButton::DPadRight),
+            ],
+            Action::Jump => vec![
+                Key(KeyCode::LeftAlt),
+                Key(KeyCode::RightAlt),
+                Pad(PadButton::South),
+            ],
+            Action::Run => vec![
+                Key(KeyCode::LeftShift),
+                Key(KeyCode::RightShift),
+                Pad(PadButton::RightTrigger),
+            ],
+            Action::Shoot => vec![
+                Key(KeyCode::LeftControl),
+                Key(KeyCode::RightControl),
+                Pad(PadButton::West),
+            ],
+            Action::SwitchWeapon => vec![Key(KeyCode::E), Pad(PadButton::North)],
+            Action::Reload => vec![Key(KeyCode::F), Pad(PadButton::East)],
+            Action::AimUp => vec![Key(KeyCode::Up), Key(KeyCode::W), Pad(PadButton::DPadUp)],
+            Action::AimDown => vec![
+                Key(KeyCode::Down),
+                Key(KeyCode::S),
+                Pad(PadButton::DPadDown),
+            ],
+            Action::Pause => vec![Key(KeyCode::Space), Key(KeyCode::P), Pad(PadButton::Start)],
+            Action::Restart => vec![Key(KeyCode::R), Pad(PadButton::Select)],
+            Action::Quit => vec![Key(KeyCode::Escape), Key(KeyCode::Q)],
+            Action::Confirm => vec![
+                Key(KeyCode::Enter),
+                Key(KeyCode::KpEnter),
+                Pad(PadButton::South),
+            ],
+            Action::Edit => vec![Key(KeyCode::F2)],
+        }
+    }
+}
+
+/// Something that can trigger an action.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    Pad(PadButton),
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Pad(button) => write!(f, "Pad{:?}", button),
+        }
+    }
+}
+
+const DEFAULT_STICK_DEADZONE: f32 = 0.3;
+
+/// controls.json. Bindings are keyed by name rather than Action so errors
+/// can say which entry is wrong.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ControlsFile {
+    #[serde(default)]
+    bindings: HashMap<String, Vec<Binding>>,
+    stick_deadzone: Option<f32>,
+}
+
+pub struct Controls {
+    bindings: HashMap<Action, Vec<Binding>>,
+    stick_deadzone: f32,
+    pads: Gamepads,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .map(|&action| (action, action.default_bindings()))
+            .collect();
+        Controls {
+            bindings,
+            stick_deadzone: DEFAULT_STICK_DEADZONE,
+            pads: Gamepads::default(),
+        }
+    }
+}
+
+impl Controls {
+    /// The default bindings, overridden by whatever `file` sets. A missing
+    /// file just means "all defaults".
+    pub fn load(file: &Path) -> Result<Self, DataError> {
+        let mut controls = Controls::default();
+        if !file.exists() {
+            return Ok(controls);
+        }
+        let config: ControlsFile = data::load_json(file)?;
+        if let Some(deadzone) = config.stick_deadzone {
+            if !(0.0..1.0).contains(&deadzone) {
+                return Err(DataError::field(
+                    file,
+                    "stick_deadzone",
+                    "must be at least 0 and less than 1",
+                ));
+            }
+            controls.stick_deadzone = deadzone;
+        }
+        let pad_bindings = config
+            .bindings
+            .values()
+            .flatten()
+            .any(|binding| matches!(binding, Binding::Pad(_)));
+        if !gamepad::SUPPORTED && (pad_bindings || config.stick_deadzone.is_some()) {
+            // Not an error, so one controls.json works for both builds
+            eprintln!(
+                "{}: gamepad bindings and stick_deadzone are ignored, this build has no \
+                 gamepad support (build with --features gamepad)",
+                file.display()
+            );
+        }
+        for (name, bindings) in config.bindings {
+            let action = Action::ALL
+                .into_iter()
+                .find(|action| format!("{:?}", action) == name)
+                .ok_or_else(|| {
+                    let names: Vec<String> =
+                        Action::ALL.iter().map(|a| format!("{:?}", a)).collect();
+                    DataError::field(
+                        file,
+                        format!("bindings.{}", name),
+                        format!("unknown action, expected one of {}", names.join(", ")),
+                    )
+                })?;
+            controls.bindings.insert(action, bindings);
+        }
+        Ok(controls)
+    }
+
+    /// Reads the gamepads; call once per frame before asking about actions.
+    pub fn update(&mut self) {
+        self.pads.update();
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        &self.bindings[&action]
+    }
+
+    /// Is anything bound to `action` held down?
+    pub fn down(&self, action: Action) -> bool {
+        let bound = self.bindings(action).iter().any(|binding| match *binding {
+            Binding::Key(key) => is_key_down(key),
+            Binding::Pad(button) => self.pads.down(button),
+        });
+        let stick = gamepad::eight_way(self.pads.stick(), self.stick_deadzone);
+        let stick = match (action, stick) {
+            (Action::MoveLeft, Some((x, _))) => x < 0,
+            (Action::MoveRight, Some((x, _))) => x > 0,
+            (Action::AimUp, Some((_, y))) => y > 0,
+            (Action::AimDown, Some((_, y))) => y < 0,
+            _ => false,
+        };
+        bound || stick
+    }
+
+    /// Was anything bound to `action` pressed this frame?
+    pub fn pressed(&self, action: Action) -> bool {
+        self.bindings(action).iter().any(|binding| match *binding {
+            Binding::Key(key) => is_key_pressed(key),
+            Binding::Pad(button) => self.pads.pressed(button),
+        })
+    }
+
+    /// This frame's gameplay input, in the form the simulation wants it.
+    pub fn frame(&self) -> InputFrame {
+        InputFrame {
+            move_left: self.down(Action::MoveLeft),
+            move_right: self.down(Action::MoveRight),
+            aim_up: self.down(Action::AimUp),
+            aim_down: self.down(Action::AimDown),
+            run: self.down(Action::Run),
+            jump: self.pressed(Action::Jump),
+            shoot: self.pressed(Action::Shoot),
+            firing: self.down(Action::Shoot),
+            switch_weapon: self.pressed(Action::SwitchWeapon),
+            reload: self.pressed(Action::Reload),
+        }
+    }
+
+    /// "Left/A = left, ..." for every action, split into lines short enough
+    /// for the HUD. Shows gamepad buttons while a gamepad is connected.
+    pub fn help_lines(&self) -> Vec<String> {
+        Action::ALL
+            .chunks(5)
+            .map(|actions| {
+                actions
+                    .iter()
+                    .map(|&action| format!("{} = {}", self.key_names(action), action.label()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .collect()
+    }
+
+    /// The keys (or gamepad buttons) bound to `action`, e.g. "Left/A".
+    pub fn key_names(&self, action: Action) -> String {
+        let pad = self.pads.connected();
+        let names: Vec<String> = self
+            .bindings(action)
+            .iter()
+            .filter(|binding| matches!(binding, Binding::Pad(_)) == pad)
+            .map(|binding| binding.to_string())
+            .collect();
+        if names.is_empty() {
+            "(unbound)".to_string()
+        } else {
+            names.join("/")
+        }
+    }
+}
+
+/// Bindings are read from their names, as written by Display.
+impl<'de> Deserialize<'de> for Binding {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(d)?;
+        let keys = KEYS.iter().map(|&key| Binding::Key(key));
+        let pads = PadButton::ALL.iter().map(|&button| Binding::Pad(button));
+        keys.chain(pads)
+            .find(|binding| binding.to_string() == name)
+            .ok_or_else(|| serde::de::Error::custom(UnknownBinding(name)))
+    }
+}
+
+struct UnknownBinding(String);
+
+impl fmt::Display for UnknownBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown key or button `{}`, expected a key name like `A`, `Key1`, `Space`, \
+             `LeftShift` or `Up`, or a gamepad button like `PadSouth` or `PadStart`",
+            self.0
+        )
+    }
+}
+
+/// Every key that can be bound. macroquad has no way to look a KeyCode up
+/// by name, so we keep the list ourselves.
+const KEYS: &[KeyCode] = &[
+    KeyCode::Space,
+    KeyCode::Apostrophe,
+    KeyCode::Comma,
+    KeyCode::Minus,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Semicolon,
+    KeyCode::Equal,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::LeftBracket,
+    KeyCode::Backslash,
+    KeyCode::RightBracket,
+    KeyCode::GraveAccent,
+    KeyCode::Escape,
+    KeyCode::Enter,
+    KeyCode::Tab,
+    KeyCode::Backspace,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Right,
+    KeyCode::Left,
+    KeyCode::Down,
+    KeyCode::Up,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::CapsLock,
+    KeyCode::ScrollLock,
+    KeyCode::NumLock,
+    KeyCode::PrintScreen,
+    KeyCode::Pause,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Kp0,
+    KeyCode::Kp1,
+    KeyCode::Kp2,
+    KeyCode::Kp3,
+    KeyCode::Kp4,
+    KeyCode::Kp5,
+    KeyCode::Kp6,
+    KeyCode::Kp7,
+    KeyCode::Kp8,
+    KeyCode::Kp9,
+    KeyCode::KpDecimal,
+    KeyCode::KpDivide,
+    KeyCode::KpMultiply,
+    KeyCode::KpSubtract,
+    KeyCode::KpAdd,
+    KeyCode::KpEnter,
+    KeyCode::KpEqual,
+    KeyCode::LeftShift,
+    KeyCode::LeftControl,
+    KeyCode::LeftAlt,
+    KeyCode::LeftSuper,
+    KeyCode::RightShift,
+    KeyCode::RightControl,
+    KeyCode::RightAlt,
+    KeyCode::RightSuper,
+    KeyCode::Menu,
+];
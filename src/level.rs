@@ -0,0 +1,439 @@
+/*
+ Level files.
+
+ Every level lives in its own JSON file in the `levels/` directory and the
+ files are loaded in file-name order (01-meadow.json, 02-..., ...). See
+ README.md for the full format. A short example:
+
+ {
+     "platforms": [[0, 400, 1000, 40], { "rect": [300, 320, 120, 20], "one_way": true }],
+     "enemies": [
+         { "pos": [320, 270], "kind": "walker", "left_bound": 300, "right_bound": 390 }
+     ],
+     "bonuses": [{ "pos": [340, 295] }],
+     "powerups": [{ "pos": [935, 325], "kind": "Speed" }],
+     "start": [100, 100],
+     "goal_x": 1050
+ }
+*/
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::archetype::Archetypes;
+use crate::camera::CameraConfig;
+use crate::data::{self, DataError};
+use crate::entities::{Bonus, Checkpoint, Enemy, PowerUp};
+use crate::moving_platform::MovingPlatform;
+use crate::physics;
+use crate::tiled;
+use crate::tilemap::{Tile, TileMap};
+use crate::{PLAYER_HEIGHT, PLAYER_WIDTH};
+
+pub const LEVELS_DIR: &str = "levels";
+/// How far below everything else in a level the kill plane is, unless the
+/// level sets `kill_y`.
+const FALL_MARGIN: f32 = 200.0;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    #[serde(default)]
+    pub platforms: Vec<Platform>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tiles: Option<TileMap>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moving_platforms: Vec<MovingPlatform>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hazards: Vec<Hazard>,
+    #[serde(default)]
+    pub enemies: Vec<Enemy>,
+    #[serde(default)]
+    pub bonuses: Vec<Bonus>,
+    #[serde(default)]
+    pub powerups: Vec<PowerUp>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checkpoints: Vec<Checkpoint>,
+    pub start: Vec2,
+    pub goal_x: f32,
+    /// Falling below this is like falling into a pit. Defaults to a little
+    /// below the bottom of the level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kill_y: Option<f32>,
+    #[serde(default)]
+    pub camera: CameraConfig,
+}
+
+impl Level {
+    /// The area the level takes up: its platforms, tiles and hazards,
+    /// everywhere the moving platforms go, the start and the goal.
+    pub fn bounds(&self) -> Rect {
+        let start = Rect::new(self.start.x, self.start.y, PLAYER_WIDTH, PLAYER_HEIGHT);
+        let mut bounds = self
+            .platforms
+            .iter()
+            .fold(start, |bounds, platform| bounds.combine_with(platform.rect));
+        if let Some(tiles) = &self.tiles {
+            bounds = bounds.combine_with(tiles.bounds());
+        }
+        for hazard in &self.hazards {
+            bounds = bounds.combine_with(hazard.rect);
+        }
+        for moving in &self.moving_platforms {
+            bounds = bounds.combine_with(moving.rect);
+            for &offset in &moving.path {
+                bounds = bounds.combine_with(moving.rect_at(offset));
+            }
+        }
+        bounds.w = bounds.w.max(self.goal_x + PLAYER_WIDTH - bounds.x);
+        bounds
+    }
+
+    /// The fixed geometry that might be collided with inside `area`: all the
+    /// free-form platforms, and the solid and one-way tiles there. Moving
+    /// platforms are added by `physics::Solids`.
+    pub fn solids_near(&self, area: Rect) -> impl Iterator<Item = Platform> + '_ {
+        let tiles = self.tiles.iter().flat_map(move |map| {
+            map.tiles_in(area).filter_map(|(rect, tile)| match tile {
+                Tile::Solid => Some(Platform {
+                    rect,
+                    one_way: false,
+                }),
+                Tile::OneWay => Some(Platform {
+                    rect,
+                    one_way: true,
+                }),
+                _ => None,
+            })
+        });
+        self.platforms.iter().copied().chain(tiles)
+    }
+
+    /// The worst of the hazards `rect` overlaps, counting hazard tiles as
+    /// spikes.
+    pub fn hazard_at(&self, rect: Rect) -> Option<Hazard> {
+        let tiles = self.tiles.iter().flat_map(|map| {
+            map.tiles_in(rect)
+                .filter(|&(_, tile)| tile == Tile::Hazard)
+                .map(|(tile_rect, _)| Hazard {
+                    rect: tile_rect,
+                    kind: HazardKind::Spikes,
+                    damage: None,
+                })
+        });
+        self.hazards
+            .iter()
+            .copied()
+            .chain(tiles)
+            .filter(|hazard| physics::overlaps(&rect, &hazard.rect))
+            .max_by_key(|hazard| hazard.damage())
+    }
+
+    /// Anything falling below this y has fallen out of the level.
+    pub fn kill_y(&self) -> f32 {
+        self.kill_y
+            .unwrap_or_else(|| self.bounds().bottom() + FALL_MARGIN)
+    }
+}
+
+/// A block of level geometry. Solid platforms stop movement from every side;
+/// one-way platforms can be jumped through from below and only hold up
+/// whatever lands on them.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "PlatformDef", into = "PlatformDef")]
+pub struct Platform {
+    pub rect: Rect,
+    pub one_way: bool,
+}
+
+/// In files a platform is `[x, y, w, h]` (solid) or
+/// `{ "rect": [x, y, w, h], "one_way": true }`.
+#[derive(Serialize, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "a platform: [x, y, w, h] or { \"rect\": [x, y, w, h], \"one_way\": bool }"
+)]
+enum PlatformDef {
+    Solid([f32; 4]),
+    Full {
+        rect: [f32; 4],
+        #[serde(default)]
+        one_way: bool,
+    },
+}
+
+impl From<PlatformDef> for Platform {
+    fn from(def: PlatformDef) -> Self {
+        let (rect, one_way) = match def {
+            PlatformDef::Solid(rect) => (rect, false),
+            PlatformDef::Full { rect, one_way } => (rect, one_way),
+        };
+        let [x, y, w, h] = rect;
+        Platform {
+            rect: Rect::new(x, y, w, h),
+            one_way,
+        }
+    }
+}
+
+impl From<Platform> for PlatformDef {
+    fn from(platform: Platform) -> Self {
+        let r = platform.rect;
+        let rect = [r.x, r.y, r.w, r.h];
+        if platform.one_way {
+            PlatformDef::Full {
+                rect,
+                one_way: true,
+            }
+        } else {
+            PlatformDef::Solid(rect)
+        }
+    }
+}
+
+/// An area that hurts the player. Spikes and lava are drawn; pits aren't,
+/// they're for the bottoms of holes the player can fall into.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hazard {
+    #[serde(with = "rect_array")]
+    pub rect: Rect,
+    pub kind: HazardKind,
+    /// What it does to the player, if not the kind's usual.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub damage: Option<Damage>,
+}
+
+impl Hazard {
+    pub fn damage(&self) -> Damage {
+        self.damage.unwrap_or(self.kind.damage())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardKind {
+    Spikes,
+    Lava,
+    Pit,
+}
+
+impl HazardKind {
+    /// Spikes and pits cost a point of health, lava kills.
+    pub fn damage(self) -> Damage {
+        match self {
+            HazardKind::Spikes | HazardKind::Pit => Damage::Health(1),
+            HazardKind::Lava => Damage::Kill,
+        }
+    }
+}
+
+/// How badly something hurts the player. In files a number of points of
+/// health, or `"kill"`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(try_from = "DamageDef", into = "DamageDef")]
+pub enum Damage {
+    Health(i32),
+    Kill,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "damage: a number of points of health, or \"kill\""
+)]
+enum DamageDef {
+    Health(i32),
+    Word(String),
+}
+
+impl TryFrom<DamageDef> for Damage {
+    type Error = String;
+
+    fn try_from(def: DamageDef) -> Result<Self, String> {
+        match def {
+            DamageDef::Health(points) if points >= 1 => Ok(Damage::Health(points)),
+            DamageDef::Health(points) => Err(format!("damage must be at least 1, not {}", points)),
+            DamageDef::Word(word) if word == "kill" => Ok(Damage::Kill),
+            DamageDef::Word(word) => Err(format!(
+                "unknown damage {:?}, expected a number or \"kill\"",
+                word
+            )),
+        }
+    }
+}
+
+impl From<Damage> for DamageDef {
+    fn from(damage: Damage) -> Self {
+        match damage {
+            Damage::Health(points) => DamageDef::Health(points),
+            Damage::Kill => DamageDef::Word("kill".to_string()),
+        }
+    }
+}
+
+/// (De)serializes a `Rect` as `[x, y, w, h]`, for `#[serde(with)]`.
+pub mod rect_array {
+    use macroquad::math::Rect;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(rect: &Rect, serializer: S) -> Result<S::Ok, S::Error> {
+        [rect.x, rect.y, rect.w, rect.h].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rect, D::Error> {
+        let [x, y, w, h] = <[f32; 4]>::deserialize(deserializer)?;
+        Ok(Rect::new(x, y, w, h))
+    }
+}
+
+/// Every level file in `dir` (`*.json`, or Tiled maps), sorted by file
+/// name.
+pub fn level_files(dir: &Path) -> Result<Vec<PathBuf>, DataError> {
+    let entries = fs::read_dir(dir).map_err(|e| DataError::new(dir, e.to_string()))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| DataError::new(dir, e.to_string()))?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "json") || tiled::is_map(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    if files.is_empty() {
+        return Err(DataError::new(
+            dir,
+            "no level files (*.json, *.tmx, *.tmj) found",
+        ));
+    }
+    Ok(files)
+}
+
+/// Loads every level file in `dir`, in order.
+pub fn load_levels(dir: &Path, archetypes: &Archetypes) -> Result<Vec<Level>, DataError> {
+    level_files(dir)?
+        .iter()
+        .map(|file| load_level(file, archetypes))
+        .collect()
+}
+
+/// Loads a level, with its enemies' kinds looked up in `archetypes`.
+pub fn load_level(file: &Path, archetypes: &Archetypes) -> Result<Level, DataError> {
+    let check = |level: &mut Level| {
+        validate(file, level)?;
+        set_archetypes(file, level, archetypes)
+    };
+    if tiled::is_map(file) {
+        let mut level = tiled::load(file, archetypes)?;
+        check(&mut level)?;
+        Ok(level)
+    } else {
+        data::load_json_checked(file, check)
+    }
+}
+
+/// Makes every enemy the archetype its `kind` names.
+fn set_archetypes(
+    file: &Path,
+    level: &mut Level,
+    archetypes: &Archetypes,
+) -> Result<(), DataError> {
+    for (i, enemy) in level.enemies.iter_mut().enumerate() {
+        let Some(archetype) = archetypes.get(&enemy.kind) else {
+            return Err(DataError::field(
+                file,
+                format!("enemies[{}].kind", i),
+                format!(
+                    "unknown kind {:?}, expected one of {}",
+                    enemy.kind,
+                    archetypes.list()
+                ),
+            ));
+        };
+        enemy.set_archetype(archetype);
+    }
+    Ok(())
+}
+
+/// Checks the things the JSON types alone can't express.
+pub fn validate(file: &Path, level: &Level) -> Result<(), DataError> {
+    for (i, platform) in level.platforms.iter().enumerate() {
+        if platform.rect.w <= 0.0 || platform.rect.h <= 0.0 {
+            return Err(DataError::field(
+                file,
+                format!("platforms[{}]", i),
+                "width and height must be positive",
+            ));
+        }
+    }
+    for (i, moving) in level.moving_platforms.iter().enumerate() {
+        let field = |name: &str| format!("moving_platforms[{}].{}", i, name);
+        if moving.rect.w <= 0.0 || moving.rect.h <= 0.0 {
+            return Err(DataError::field(
+                file,
+                field("rect"),
+                "width and height must be positive",
+            ));
+        }
+        if moving.path.is_empty() {
+            return Err(DataError::field(
+                file,
+                field("path"),
+                "needs at least one point to move to",
+            ));
+        }
+        if moving.speed.is_nan() || moving.speed <= 0.0 {
+            return Err(DataError::field(file, field("speed"), "must be positive"));
+        }
+        if moving.pause.is_nan() || moving.pause < 0.0 {
+            return Err(DataError::field(file, field("pause"), "can't be negative"));
+        }
+    }
+    for (i, hazard) in level.hazards.iter().enumerate() {
+        if hazard.rect.w <= 0.0 || hazard.rect.h <= 0.0 {
+            return Err(DataError::field(
+                file,
+                format!("hazards[{}].rect", i),
+                "width and height must be positive",
+            ));
+        }
+    }
+    for (i, enemy) in level.enemies.iter().enumerate() {
+        if let (Some(left), Some(right)) = (enemy.left_bound, enemy.right_bound)
+            && left > right
+        {
+            return Err(DataError::field(
+                file,
+                format!("enemies[{}].right_bound", i),
+                format!("right_bound ({}) is left of left_bound ({})", right, left),
+            ));
+        }
+    }
+    if !level.goal_x.is_finite() {
+        return Err(DataError::field(file, "goal_x", "must be a number"));
+    }
+    if level.kill_y.is_some_and(|y| !y.is_finite()) {
+        return Err(DataError::field(file, "kill_y", "must be a number"));
+    }
+    let camera = &level.camera;
+    if camera.dead_zone.x < 0.0 || camera.dead_zone.y < 0.0 {
+        return Err(DataError::field(
+            file,
+            "camera.dead_zone",
+            "can't be negative",
+        ));
+    }
+    if camera.smoothing < 0.0 {
+        return Err(DataError::field(
+            file,
+            "camera.smoothing",
+            "can't be negative",
+        ));
+    }
+    Ok(())
+}
@@ -6,11 +6,44 @@
 */
 
 use macroquad::{miniquad::*, prelude::*};
-
-
-
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod ai;
+mod animation;
+mod archetype;
+mod camera;
+mod data;
+mod editor;
+mod entities;
+mod game;
+mod gamepad;
+mod input;
+mod level;
+mod moving_platform;
+mod physics;
+mod render;
+mod replay;
+mod rng;
+mod save;
+mod scores;
+mod sprites;
+mod tiled;
+mod tilemap;
+mod weapon;
+mod world;
+
+use archetype::{Archetypes, ENEMIES_FILE};
+use game::Game;
+use input::{CONTROLS_FILE, Controls};
+use level::LEVELS_DIR;
+use replay::{LAST_REPLAY_FILE, Playback, Replay};
+use sprites::{SPRITES_FILE, Sprites};
+use world::World;
 
 const GAME_SPEED: f32 = 1.0;
+const STEP_DT: f32 = 1.0 / 120.0; // the simulation always advances in steps of this size
+const MAX_FRAME_TIME: f32 = 0.25; // after a longer hitch the game slows down instead of catching up
 const PLAYER_WIDTH: f32 = 37.0;
 const PLAYER_HEIGHT: f32 = 50.0;
 const BASE_MOVE_SPEED: f32 = 200.0;
@@ -21,894 +54,202 @@ const JUMP_SPEED: f32 = 400.0;
 const HIGH_JUMP_SPEED: f32 = 650.0; // NEW
 const BULLET_SPEED: f32 = 500.0;
 const KILL_BOUNCE: f32 = 0.9; // How high will character go after bouncing on an enemy?
-const ENEMY_WIDTH: f32 = 28.0;
-const ENEMY_HEIGHT: f32 = 45.0;
-const ENEMY_SPEED: f32 = 60.0;
+const KNOCKBACK_SPEED: f32 = 250.0; // how hard a hit throws the player sideways...
+const KNOCKBACK_LIFT: f32 = 250.0; // ...and up
+const KNOCKBACK_TIME: f32 = 0.3; // seconds the controls don't steer after a hit
 const BONUS_SIZE: f32 = 20.0;
 const POWERUP_SIZE: f32 = 20.0;
 const MAX_HEALTH: i32 = 3;
 const TIMER_BOOST: f32 = 3.0;
 
-#[derive(Clone, Copy)]
-enum PowerUpType {
-    Health,
-    Speed,
-    Invincibility,
-    HighJump, // NEW
-}
-
-#[derive(Clone)]
-struct PowerUp {
-    pos: Vec2,
-    kind: PowerUpType,
-    collected: bool,
-}
-
-impl PowerUp {
-    fn draw(&self, camera_x: f32) {
-        if !self.collected {
-            let color = match self.kind {
-                PowerUpType::Health => PINK,
-                PowerUpType::Speed => ORANGE,
-                PowerUpType::Invincibility => PURPLE,
-                PowerUpType::HighJump => BLUE, // NEW
-            };
-            draw_circle(
-                self.pos.x - camera_x + POWERUP_SIZE / 2.0,
-                self.pos.y + POWERUP_SIZE / 2.0,
-                POWERUP_SIZE / 2.0,
-                color,
-            );
-        }
-    }
-    fn rect(&self) -> Rect {
-        Rect::new(self.pos.x, self.pos.y, POWERUP_SIZE, POWERUP_SIZE)
-    }
-}
-
-#[derive(Clone)]
-struct Enemy {
-    pos: Vec2,
-    vel: Vec2,
-    left_bound: f32,
-    right_bound: f32,
-    alive: bool,
-    gravity: f32,
-    can_be_jumped_on: bool, // NEW
-}
-
-impl Enemy {
-    fn update(&mut self, dt: f32, platforms: &[Rect]) {
-        if !self.alive {
+/// Shown instead of the game when something needed at startup is broken.
+async fn show_error(message: &str) {
+    loop {
+        if is_key_down(KeyCode::Escape) || is_key_down(KeyCode::Q) {
             return;
         }
-        self.pos.x += self.vel.x * dt;
-        if self.pos.x < self.left_bound {
-            self.pos.x = self.left_bound;
-            self.vel.x = ENEMY_SPEED;
-        }
-        if self.pos.x > self.right_bound {
-            self.pos.x = self.right_bound;
-            self.vel.x = -ENEMY_SPEED;
-        }
-        self.vel.y += self.gravity * dt;
-        let mut new_pos = self.pos;
-        new_pos.y += self.vel.y * dt;
-        let enemy_rect = Rect::new(new_pos.x, new_pos.y, ENEMY_WIDTH, ENEMY_HEIGHT);
-
-        for platform in platforms {
-            if enemy_rect.overlaps(platform) {
-                if self.vel.y > 0.0 && self.pos.y + ENEMY_HEIGHT <= platform.y {
-                    new_pos.y = platform.y - ENEMY_HEIGHT;
-                    self.vel.y = 0.0;
-                }
-            }
-        }
-        self.pos = new_pos;
-    }
-
-    fn draw(&self, camera_x: f32) {
-        if self.alive {
-            let x = self.pos.x - camera_x;
-            let y = self.pos.y;
-            // Head
-            let head_color = if self.can_be_jumped_on { LIME } else { RED };
-            draw_circle(x + ENEMY_WIDTH / 2.0, y + 12.0, 10.0, head_color);
-            // "Hat" for jump-on enemies
-            if self.can_be_jumped_on {
-                draw_rectangle(x + ENEMY_WIDTH / 2.0 - 8.0, y + 2.0, 16.0, 4.0, DARKGREEN);
-            }
-            // Body
-            draw_rectangle(x + ENEMY_WIDTH / 2.0 - 5.0, y + 22.0, 10.0, 14.0, RED);
-            // Arms
-            draw_line(x + ENEMY_WIDTH / 2.0, y + 24.0, x, y + 28.0, 2.0, RED);
-            draw_line(
-                x + ENEMY_WIDTH / 2.0,
-                y + 24.0,
-                x + ENEMY_WIDTH,
-                y + 28.0,
-                2.0,
-                RED,
-            );
-            // Legs
-            draw_line(
-                x + ENEMY_WIDTH / 2.0,
-                y + 36.0,
-                x + 4.0,
-                y + ENEMY_HEIGHT,
-                2.0,
-                RED,
-            );
-            draw_line(
-                x + ENEMY_WIDTH / 2.0,
-                y + 36.0,
-                x + ENEMY_WIDTH - 4.0,
-                y + ENEMY_HEIGHT,
-                2.0,
-                RED,
-            );
+        clear_background(LIGHTGRAY);
+        for (i, line) in message.lines().enumerate() {
+            draw_text(line, 20.0, 40.0 + i as f32 * 30.0, 24.0, RED);
         }
+        draw_text("Esc/q = quit", 20.0, screen_height() - 20.0, 24.0, BLACK);
+        next_frame().await
     }
-    fn rect(&self) -> Rect {
-        Rect::new(self.pos.x, self.pos.y, ENEMY_WIDTH, ENEMY_HEIGHT)
-    }
-}
-
-#[derive(Clone)]
-struct Bonus {
-    pos: Vec2,
-    collected: bool,
 }
 
-impl Bonus {
-    fn draw(&self, camera_x: f32) {
-        if !self.collected {
-            draw_circle(
-                self.pos.x - camera_x + BONUS_SIZE / 2.0,
-                self.pos.y + BONUS_SIZE / 2.0,
-                BONUS_SIZE / 2.0,
-                GOLD,
-            );
-        }
-    }
-    fn rect(&self) -> Rect {
-        Rect::new(self.pos.x, self.pos.y, BONUS_SIZE, BONUS_SIZE)
-    }
-}
+const USAGE: &str = "usage: adventure [--record <file>] | [--replay <file> [--verify]]";
 
-struct Bullet {
-    pos: Vec2,
-    vel: Vec2,
-    alive: bool,
+/// Command line options.
+struct Args {
+    /// Play this recording back instead of taking input.
+    replay: Option<PathBuf>,
+    /// Where the recording of this session is saved.
+    record: PathBuf,
+    /// Play the replay without a window and report whether it matched.
+    verify: bool,
 }
 
-impl Bullet {
-    fn update(&mut self, dt: f32) {
-        if !self.alive {
-            return;
-        }
-        self.pos += self.vel * dt;
-        if self.pos.x < 0.0 || self.pos.x > 3000.0 || self.pos.y < 0.0 || self.pos.y > 2000.0 {
-            self.alive = false;
-        }
-    }
-    fn draw(&self, camera_x: f32) {
-        if self.alive {
-            draw_rectangle(self.pos.x - camera_x, self.pos.y, 10.0, 4.0, YELLOW);
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        replay: None,
+        record: PathBuf::from(LAST_REPLAY_FILE),
+        verify: false,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--replay" => args.replay = Some(argv.next().ok_or("--replay needs a file")?.into()),
+            "--record" => args.record = argv.next().ok_or("--record needs a file")?.into(),
+            "--verify" => args.verify = true,
+            _ => return Err(format!("unknown argument {}", arg)),
         }
     }
-    fn rect(&self) -> Rect {
-        Rect::new(self.pos.x, self.pos.y, 10.0, 4.0)
+    if args.verify && args.replay.is_none() {
+        return Err("--verify needs --replay <file>".to_string());
     }
+    Ok(args)
 }
 
-struct Player {
-    pos: Vec2,
-    vel: Vec2,
-    on_ground: bool,
-    facing_right: bool,
-    health: i32,
-    score: i32,
-    alive: bool,
-    speed_timer: f32,
-    invincible_timer: f32,
-    high_jump_timer: f32, // NEW
-    prev_y: f32,          // NEW, for jump-on detection
-    is_running: bool,
-}
-
-impl Player {
-    fn update(&mut self, dt: f32, platforms: &[Rect]) {
-        if !self.alive {
-            return;
-        }
-
-        self.prev_y = self.pos.y; // NEW
-        let mut input = 0.0;
-        if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {
-            input -= 1.0;
-        }
-        if is_key_down(KeyCode::D) || is_key_down(KeyCode::Right) {
-            input += 1.0;
-        }
-
-        if is_key_down(KeyCode::LeftControl) {
-            self.is_running = true;
-        } else {
-            self.is_running = false;
-        }
-
-        //let move_speed = BASE_MOVE_SPEED * boost + if self.speed_timer > 0.0 { SPEED_BOOST } else { 0.0 };
-
-        let move_speed = if self.is_running {
-            RUNNING_SPEED
-        } else {
-            BASE_MOVE_SPEED
-        } + if self.speed_timer > 0.0 {
-            SPEED_BOOST
-        } else {
-            0.0
-        };
-
-        self.vel.x = input * move_speed;
-        if input != 0.0 {
-            self.facing_right = input > 0.0;
-        }
-        let jump_speed = if self.high_jump_timer > 0.0 {
-            HIGH_JUMP_SPEED
-        } else {
-            JUMP_SPEED
-        }; // NEW
-        if self.on_ground && (is_key_pressed(KeyCode::LeftAlt) || is_key_pressed(KeyCode::RightAlt))
-        {
-            self.vel.y = -jump_speed;
-            self.on_ground = false;
+/// Runs a replay headless; the exit code is 0 if it ended where it was
+/// recorded to.
+fn verify_replay(file: &Path) -> i32 {
+    let archetypes = match Archetypes::load(Path::new(ENEMIES_FILE)) {
+        Ok(archetypes) => archetypes,
+        Err(err) => {
+            eprintln!("error loading enemies: {}", err);
+            return 2;
         }
-        if self.speed_timer > 0.0 {
-            self.speed_timer -= dt / TIMER_BOOST;
+    };
+    let levels = match level::load_levels(Path::new(LEVELS_DIR), &archetypes) {
+        Ok(levels) => levels,
+        Err(err) => {
+            eprintln!("error loading levels: {}", err);
+            return 2;
         }
-        if self.invincible_timer > 0.0 {
-            self.invincible_timer -= dt / TIMER_BOOST;
+    };
+    let replay = match Replay::load(file) {
+        Ok(replay) => replay,
+        Err(err) => {
+            eprintln!("error loading replay: {}", err);
+            return 2;
         }
-        if self.high_jump_timer > 0.0 {
-            self.high_jump_timer -= dt / TIMER_BOOST;
+    };
+    let steps = replay.inputs.len();
+    let mut world = World::new(levels, replay.seed);
+    match replay::verify(replay, &mut world) {
+        Ok(true) => {
+            println!("replay matches after {} steps", steps);
+            0
         }
-
-        self.vel.y += GRAVITY * dt;
-        let mut new_pos = self.pos + self.vel * dt;
-        let player_rect = Rect::new(new_pos.x, new_pos.y, PLAYER_WIDTH, PLAYER_HEIGHT);
-
-        self.on_ground = false;
-        for platform in platforms {
-            if player_rect.overlaps(platform) {
-                if self.vel.y > 0.0 && self.pos.y + PLAYER_HEIGHT <= platform.y {
-                    new_pos.y = platform.y - PLAYER_HEIGHT;
-                    self.vel.y = 0.0;
-                    self.on_ground = true;
-                }
-            }
+        Ok(false) => {
+            println!("replay diverged after {} steps", steps);
+            1
         }
-        if new_pos.y > 2000.0 {
-            new_pos = vec2(100.0, 100.0);
-            self.vel = Vec2::ZERO;
+        Err(err) => {
+            eprintln!("error in replay: {}: {}", file.display(), err);
+            2
         }
-        self.pos = new_pos;
     }
+}
 
-    fn draw(&self, camera_x: f32) {
-        let x = self.pos.x - camera_x;
-        let y = self.pos.y;
-        // Head
-        draw_circle(x + PLAYER_WIDTH / 2.0, y + 14.0, 12.0, YELLOW);
-        // Body
-        let mut body_color = BLUE;
-        if !self.alive {
-            body_color = GRAY;
-        } else if self.invincible_timer > 0.0 {
-            body_color = YELLOW;
-        } else if self.high_jump_timer > 0.0 {
-            body_color = BLUE;
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
         }
-        draw_rectangle(
-            x + PLAYER_WIDTH / 2.0 - 5.0,
-            y + 26.0,
-            10.0,
-            16.0,
-            body_color,
-        );
-        // Arms
-        draw_line(
-            x + PLAYER_WIDTH / 2.0,
-            y + 28.0,
-            x,
-            y + 35.0,
-            3.0,
-            body_color,
-        );
-        draw_line(
-            x + PLAYER_WIDTH / 2.0,
-            y + 28.0,
-            x + PLAYER_WIDTH,
-            y + 35.0,
-            3.0,
-            body_color,
-        );
-        // Legs
-        draw_line(
-            x + PLAYER_WIDTH / 2.0,
-            y + 42.0,
-            x + 5.0,
-            y + PLAYER_HEIGHT,
-            3.0,
-            body_color,
-        );
-        draw_line(
-            x + PLAYER_WIDTH / 2.0,
-            y + 42.0,
-            x + PLAYER_WIDTH - 5.0,
-            y + PLAYER_HEIGHT,
-            3.0,
-            body_color,
-        );
-    }
-
-    fn rect(&self) -> Rect {
-        Rect::new(self.pos.x, self.pos.y, PLAYER_WIDTH, PLAYER_HEIGHT)
-    }
-
-    fn reset(&mut self, pos: Vec2) {
-        self.pos = pos;
-        self.vel = Vec2::ZERO;
-        self.prev_y = pos.y;
+    };
+    if args.verify {
+        let file = args.replay.as_deref().unwrap();
+        std::process::exit(verify_replay(file));
     }
+    macroquad::Window::new("Adventure Game: Powerups & Levels", run(args));
 }
 
-#[derive(Clone)]
-struct Level {
-    platforms: Vec<Rect>,
-    enemies: Vec<Enemy>,
-    bonuses: Vec<Bonus>,
-    powerups: Vec<PowerUp>,
-    start: Vec2,
-    goal_x: f32,
-}
-
-fn make_levels() -> Vec<Level> {
-    vec![
-        Level {
-            platforms: vec![
-                Rect::new(0.0, 400.0, 1000.0, 40.0),
-                Rect::new(300.0, 320.0, 120.0, 20.0),
-                Rect::new(600.0, 260.0, 100.0, 20.0),
-                Rect::new(900.0, 350.0, 140.0, 20.0),
-            ],
-            enemies: vec![
-                Enemy {
-                    pos: vec2(320.0, 270.0),
-                    vel: vec2(ENEMY_SPEED, 0.0),
-                    left_bound: 300.0,
-                    right_bound: 390.0,
-                    alive: true,
-                    gravity: GRAVITY,
-                    can_be_jumped_on: true, // NEW
-                },
-                Enemy {
-                    pos: vec2(620.0, 210.0),
-                    vel: vec2(-ENEMY_SPEED, 0.0),
-                    left_bound: 600.0,
-                    right_bound: 690.0,
-                    alive: true,
-                    gravity: GRAVITY,
-                    can_be_jumped_on: false, // NEW
-                },
-            ],
-            bonuses: vec![
-                Bonus {
-                    pos: vec2(340.0, 295.0),
-                    collected: false,
-                },
-                Bonus {
-                    pos: vec2(650.0, 235.0),
-                    collected: false,
-                },
-            ],
-            powerups: vec![
-                PowerUp {
-                    pos: vec2(935.0, 325.0),
-                    kind: PowerUpType::Speed,
-                    collected: false,
-                },
-                PowerUp {
-                    pos: vec2(700.0, 235.0),
-                    kind: PowerUpType::HighJump,
-                    collected: false,
-                }, // NEW
-                PowerUp {
-                    pos: vec2(200.0, 235.0),
-                    kind: PowerUpType::Health,
-                    collected: false,
-                }, // NEW
-            ],
-            start: vec2(100.0, 100.0),
-            goal_x: 1050.0,
-        },
-        Level {
-            platforms: vec![
-                Rect::new(0.0, 400.0, 1400.0, 40.0),
-                Rect::new(200.0, 320.0, 120.0, 20.0),
-                Rect::new(600.0, 250.0, 100.0, 20.0),
-                Rect::new(1000.0, 200.0, 90.0, 20.0),
-                Rect::new(1200.0, 320.0, 100.0, 20.0),
-            ],
-            enemies: vec![
-                Enemy {
-                    pos: vec2(220.0, 270.0),
-                    vel: vec2(ENEMY_SPEED, 0.0),
-                    left_bound: 200.0,
-                    right_bound: 320.0,
-                    alive: true,
-                    gravity: GRAVITY,
-                    can_be_jumped_on: true, // NEW
-                },
-                Enemy {
-                    pos: vec2(620.0, 200.0),
-                    vel: vec2(-ENEMY_SPEED, 0.0),
-                    left_bound: 600.0,
-                    right_bound: 700.0,
-                    alive: true,
-                    gravity: GRAVITY,
-                    can_be_jumped_on: false, // NEW
-                },
-                Enemy {
-                    pos: vec2(1020.0, 150.0),
-                    vel: vec2(ENEMY_SPEED, 0.0),
-                    left_bound: 1000.0,
-                    right_bound: 1090.0,
-                    alive: true,
-                    gravity: GRAVITY,
-                    can_be_jumped_on: true, // NEW
-                },
-            ],
-            bonuses: vec![
-                Bonus {
-                    pos: vec2(650.0, 225.0),
-                    collected: false,
-                },
-                Bonus {
-                    pos: vec2(1250.0, 295.0),
-                    collected: false,
-                },
-            ],
-            powerups: vec![
-                PowerUp {
-                    pos: vec2(1100.0, 175.0),
-                    kind: PowerUpType::Invincibility,
-                    collected: false,
-                },
-                PowerUp {
-                    pos: vec2(1300.0, 295.0),
-                    kind: PowerUpType::HighJump,
-                    collected: false,
-                }, // NEW
-            ],
-            start: vec2(100.0, 100.0),
-            goal_x: 1450.0,
-        },
-        Level {
-            platforms: vec![
-                Rect::new(0.0, 400.0, 1000.0, 40.0),
-                Rect::new(300.0, 320.0, 120.0, 20.0),
-                Rect::new(400.0, 260.0, 100.0, 20.0),
-                Rect::new(400.0, 350.0, 140.0, 20.0),
-            ],
-            enemies: vec![
-                Enemy {
-                    pos: vec2(320.0, 270.0),
-                    vel: vec2(ENEMY_SPEED, 0.0),
-                    left_bound: 300.0,
-                    right_bound: 390.0,
-                    alive: true,
-                    gravity: GRAVITY,
-                    can_be_jumped_on: true, // NEW
-                },
-                Enemy {
-                    pos: vec2(620.0, 210.0),
-                    vel: vec2(-ENEMY_SPEED, 0.0),
-                    left_bound: 600.0,
-                    right_bound: 690.0,
-                    alive: true,
-                    gravity: GRAVITY,
-                    can_be_jumped_on: false, // NEW
-                },
-            ],
-            bonuses: vec![
-                Bonus {
-                    pos: vec2(340.0, 295.0),
-                    collected: false,
-                },
-                Bonus {
-                    pos: vec2(650.0, 235.0),
-                    collected: false,
-                },
-            ],
-            powerups: vec![
-                PowerUp {
-                    pos: vec2(935.0, 325.0),
-                    kind: PowerUpType::Speed,
-                    collected: false,
-                },
-                PowerUp {
-                    pos: vec2(700.0, 235.0),
-                    kind: PowerUpType::HighJump,
-                    collected: false,
-                }, // NEW
-            ],
-            start: vec2(100.0, 100.0),
-            goal_x: 1050.0,
-        },
-    ]
-}
-
-#[macroquad::main("Adventure Game: Powerups & Levels")]
-async fn main() {
-    //    let mut levels = make_levels();
-    let levels = make_levels();
-
-    let mut current_level = 0;
-
-    let mut player = Player {
-        pos: levels[0].start,
-        vel: Vec2::ZERO,
-        on_ground: false,
-        facing_right: true,
-        health: MAX_HEALTH,
-        score: 0,
-        alive: true,
-        speed_timer: 0.0,
-        invincible_timer: 0.0,
-        high_jump_timer: 0.0,
-        prev_y: levels[0].start.y,
-        is_running: false,
-    };
-
-    let mut enemies = levels[0].enemies.clone();
-    let mut bonuses = levels[0].bonuses.clone();
-    let mut powerups = levels[0].powerups.clone();
-    let mut bullets: Vec<Bullet> = Vec::new();
-    let mut shoot_cooldown = 0.0;
-
-    let mut game_won = false;
-
-    loop {
-        let dt = get_frame_time() * GAME_SPEED;
-        let platforms: &[Rect] = if current_level < levels.len() {
-            &levels[current_level].platforms
-        } else {
-            &[] as &[Rect]
-        };
-
-        if player.alive && !game_won {
-            player.update(dt, platforms);
-        }
-
-        let camera_x = player.pos.x - screen_width() / 2.0 + PLAYER_WIDTH / 2.0;
-
-        if is_key_down(KeyCode::Escape) || is_key_down(KeyCode::Q) {
+async fn run(args: Args) {
+    let archetypes = match Archetypes::load(Path::new(ENEMIES_FILE)) {
+        Ok(archetypes) => archetypes,
+        Err(err) => {
+            eprintln!("error loading enemies: {}", err);
+            show_error(&format!("Error loading enemies:\n{}", err)).await;
             return;
         }
+    };
 
-        if is_key_down(KeyCode::Space) {
-            // TODO
-        }
-
-        shoot_cooldown -= dt;
-        if player.alive
-            && !game_won
-            && (is_key_pressed(KeyCode::LeftControl) || is_key_pressed(KeyCode::RightControl))
-            && shoot_cooldown <= 0.0
-        {
-            let dir = if player.facing_right { 1.0 } else { -1.0 };
-
-            if is_key_down(KeyCode::Up) && {
-                is_key_down(KeyCode::Left) || is_key_down(KeyCode::Right)
-            } {
-                bullets.push(Bullet {
-                    pos: vec2(
-                        player.pos.x + PLAYER_WIDTH / 2.0,
-                        player.pos.y + PLAYER_HEIGHT / 2.0,
-                    ),
-                    vel: vec2(dir * BULLET_SPEED, -BULLET_SPEED),
-                    alive: true,
-                });
-            } else if is_key_down(KeyCode::Up) || is_key_down(KeyCode::W) {
-                bullets.push(Bullet {
-                    pos: vec2(player.pos.x + PLAYER_WIDTH / 2.0, player.pos.y),
-                    vel: vec2(0.0, -BULLET_SPEED),
-                    alive: true,
-                });
-            }
-            if is_key_down(KeyCode::Down) && {
-                is_key_down(KeyCode::Left) || is_key_down(KeyCode::Right)
-            } {
-                bullets.push(Bullet {
-                    pos: vec2(
-                        player.pos.x + PLAYER_WIDTH / 2.0 + dir * 18.0,
-                        player.pos.y + PLAYER_HEIGHT / 2.0,
-                    ),
-                    vel: vec2(dir * BULLET_SPEED, BULLET_SPEED),
-                    alive: true,
-                });
-            } else if is_key_down(KeyCode::Down) || is_key_down(KeyCode::S) {
-                bullets.push(Bullet {
-                    pos: vec2(
-                        player.pos.x + PLAYER_WIDTH / 2.0,
-                        player.pos.y + PLAYER_HEIGHT,
-                    ),
-                    vel: vec2(0.0, BULLET_SPEED),
-                    alive: true,
-                });
-
-            // We're not moving right or left
-            } else if !is_key_down(KeyCode::Up) {
-                bullets.push(Bullet {
-                    pos: vec2(
-                        player.pos.x + PLAYER_WIDTH / 2.0 + dir * 18.0,
-                        player.pos.y + PLAYER_HEIGHT / 2.0,
-                    ),
-                    vel: vec2(dir * BULLET_SPEED, 0.0),
-                    alive: true,
-                });
-            }
-            shoot_cooldown = 0.2; // edited
-        }
-
-        for bullet in &mut bullets {
-            bullet.update(dt);
-        }
-        bullets.retain(|b| b.alive);
-
-        for enemy in &mut enemies {
-            enemy.update(dt, platforms);
-        }
-
-        // Bullet-enemy collision
-        for bullet in &mut bullets {
-            if !bullet.alive {
-                continue;
-            }
-            for enemy in &mut enemies {
-                if enemy.alive && bullet.rect().overlaps(&enemy.rect()) {
-                    enemy.alive = false;
-                    bullet.alive = false;
-                    if player.alive {
-                        player.score += 100;
-                    }
-                }
-            }
-        }
-
-        // Jump-on-enemy logic and player-enemy collision
-        if player.alive && !game_won {
-            let mut jumped_on_any = false;
-            for enemy in &mut enemies {
-                if !enemy.alive {
-                    continue;
-                }
-                let player_rect = player.rect();
-                let enemy_rect = enemy.rect();
-                let is_colliding = player_rect.overlaps(&enemy_rect);
-
-                // Jump on enemy from above
-                let player_was_above = player.prev_y + PLAYER_HEIGHT <= enemy.pos.y + 4.0; // fudge factor
-                if enemy.can_be_jumped_on && is_colliding && player.vel.y > 0.0 && player_was_above
-                {
-                    enemy.alive = false;
-                    player.vel.y = -JUMP_SPEED * KILL_BOUNCE; // bounce up
-                    player.score += 150;
-                    jumped_on_any = true;
-                }
-            }
-
-            // If not jumping on any enemy, regular collision (damage)
-            if !jumped_on_any && player.invincible_timer <= 0.0 {
-                for enemy in &enemies {
-                    if enemy.alive && player.rect().overlaps(&enemy.rect()) {
-                        player.health -= 1;
-                        if player.health <= 0 {
-                            player.alive = false;
-                        }
-                        player.reset(levels[current_level].start);
-                        break;
-                    }
-                }
-            }
-        }
-
-        if player.alive && !game_won {
-            for bonus in &mut bonuses {
-                if !bonus.collected && player.rect().overlaps(&bonus.rect()) {
-                    bonus.collected = true;
-                    player.score += 50;
-                }
-            }
-        }
-
-        if player.alive && !game_won {
-            for powerup in &mut powerups {
-                if !powerup.collected && player.rect().overlaps(&powerup.rect()) {
-                    powerup.collected = true;
-                    match powerup.kind {
-                        PowerUpType::Health => {
-                            player.health = MAX_HEALTH.min(player.health + 1);
-                        }
-                        PowerUpType::Speed => {
-                            player.speed_timer = 5.0;
-                        }
-                        PowerUpType::Invincibility => {
-                            player.invincible_timer = 5.0;
-                        }
-                        PowerUpType::HighJump => {
-                            // NEW
-                            player.high_jump_timer = 5.0;
-                        }
-                    }
-                }
-            }
+    let loaded = level::level_files(Path::new(LEVELS_DIR)).and_then(|files| {
+        let levels: Result<Vec<_>, _> = files
+            .iter()
+            .map(|file| level::load_level(file, &archetypes))
+            .collect();
+        levels.map(|levels| (files, levels))
+    });
+    let (level_files, levels) = match loaded {
+        Ok(levels) => levels,
+        Err(err) => {
+            eprintln!("error loading levels: {}", err);
+            show_error(&format!("Error loading levels:\n{}", err)).await;
+            return;
         }
+    };
 
-        // Level progression and win logic
-        if !game_won && player.pos.x > levels[current_level].goal_x {
-            current_level += 1;
-            if current_level >= levels.len() {
-                game_won = true;
-            } else {
-                player.reset(levels[current_level].start);
-                enemies = levels[current_level].enemies.clone();
-                bonuses = levels[current_level].bonuses.clone();
-                powerups = levels[current_level].powerups.clone();
-                bullets.clear();
-                player.speed_timer = 0.0;
-                player.invincible_timer = 0.0;
-                player.high_jump_timer = 0.0;
-                continue;
-            }
+    let controls = match Controls::load(Path::new(CONTROLS_FILE)) {
+        Ok(controls) => controls,
+        Err(err) => {
+            eprintln!("error loading controls: {}", err);
+            show_error(&format!("Error loading controls:\n{}", err)).await;
+            return;
         }
+    };
 
-        clear_background(LIGHTGRAY);
-
-        if game_won {
-            draw_text(
-                "YOU WIN!",
-                screen_width() / 2.0 - 90.0,
-                screen_height() / 2.0,
-                56.0,
-                DARKGREEN,
-            );
-            draw_text(
-                &format!("Final Score: {}", player.score),
-                screen_width() / 2.0 - 120.0,
-                screen_height() / 2.0 + 60.0,
-                40.0,
-                BLACK,
-            );
-            draw_text(
-                "Press R to Restart",
-                screen_width() / 2.0 - 110.0,
-                screen_height() / 2.0 + 120.0,
-                32.0,
-                BLACK,
-            );
-            if is_key_pressed(KeyCode::R) {
-                game_won = false;
-                current_level = 0;
-                player = Player {
-                    pos: levels[0].start,
-                    vel: Vec2::ZERO,
-                    on_ground: false,
-                    facing_right: true,
-                    health: MAX_HEALTH,
-                    score: 0,
-                    alive: true,
-                    speed_timer: 0.0,
-                    invincible_timer: 0.0,
-                    high_jump_timer: 0.0,
-                    prev_y: levels[0].start.y,
-                    is_running: false,
-                };
-                enemies = levels[0].enemies.clone();
-                bonuses = levels[0].bonuses.clone();
-                powerups = levels[0].powerups.clone();
-                bullets.clear();
+    let mut playback = match &args.replay {
+        Some(file) => match Replay::load(file) {
+            Ok(replay) => Some(Playback::new(replay)),
+            Err(err) => {
+                eprintln!("error loading replay: {}", err);
+                show_error(&format!("Error loading replay:\n{}", err)).await;
+                return;
             }
-            next_frame().await;
-            continue;
-        }
-
-        for platform in platforms {
-            draw_rectangle(
-                platform.x - camera_x,
-                platform.y,
-                platform.w,
-                platform.h,
-                DARKGREEN,
-            );
-        }
-        for enemy in &enemies {
-            enemy.draw(camera_x);
-        }
-        for bonus in &bonuses {
-            bonus.draw(camera_x);
-        }
-        for powerup in &powerups {
-            powerup.draw(camera_x);
-        }
-        player.draw(camera_x);
-        for bullet in &bullets {
-            bullet.draw(camera_x);
-        }
-
-        let health_str = format!("Health: {}/{}", player.health, MAX_HEALTH);
-        draw_text(&health_str, 10.0, 30.0, 30.0, RED);
-        let score_str = format!("Score: {}", player.score);
-        draw_text(&score_str, 10.0, 65.0, 30.0, BLACK);
-        draw_text(
-            "Arrow = move, Ctrl = run/shoot, Alt = jump, Esc/q = quit",
-            10.0,
-            100.0,
-            24.0,
-            BLACK,
-        );
+        },
+        None => None,
+    };
 
-        let position = format!("player.pos: {:?} camera_x: {:?}", player.pos, camera_x);
-        draw_text(position.as_str(), 400.0, 20.0, 24.0, BLACK);
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_nanos() as u64);
+    let mut world = World::new(levels, seed);
+    if let Some(playback) = &mut playback
+        && let Err(err) = playback.rewind(&mut world)
+    {
+        eprintln!("error in replay: {}", err);
+        show_error(&format!("Error in replay:\n{}", err)).await;
+        return;
+    }
+    prevent_quit();
 
-        if player.speed_timer > 0.0 {
-            draw_text("SPEED!", 10.0, 130.0, 28.0, ORANGE);
-        }
-        if player.invincible_timer > 0.0 {
-            draw_text("INVINCIBLE!", 10.0, 160.0, 28.0, YELLOW);
-        }
-        if player.high_jump_timer > 0.0 {
-            draw_text("HIGH JUMP!", 10.0, 190.0, 28.0, BLUE);
+    // Without the art the game still runs, drawn with shapes
+    let sprites = match Sprites::load(Path::new(SPRITES_FILE)).await {
+        Ok(sprites) => sprites,
+        Err(err) => {
+            eprintln!("not using sprites: {}", err);
+            Sprites::default()
         }
+    };
 
-        if !player.alive {
-            draw_text(
-                "GAME OVER",
-                screen_width() / 2.0 - 100.0,
-                screen_height() / 2.0,
-                48.0,
-                RED,
-            );
-            draw_text(
-                "Press R to Restart",
-                screen_width() / 2.0 - 110.0,
-                screen_height() / 2.0 + 50.0,
-                32.0,
-                BLACK,
-            );
-            if is_key_pressed(KeyCode::R) {
-                player = Player {
-                    pos: levels[current_level].start,
-                    vel: Vec2::ZERO,
-                    on_ground: false,
-                    facing_right: true,
-                    health: MAX_HEALTH,
-                    score: 0,
-                    alive: true,
-                    speed_timer: 0.0,
-                    invincible_timer: 0.0,
-                    high_jump_timer: 0.0,
-                    prev_y: levels[current_level].start.y,
-                    is_running: false,
-                };
-                enemies = levels[current_level].enemies.clone();
-                bonuses = levels[current_level].bonuses.clone();
-                powerups = levels[current_level].powerups.clone();
-                bullets.clear();
-            }
+    let mut game = Game::new(
+        world,
+        controls,
+        sprites,
+        archetypes,
+        playback,
+        args.record,
+        level_files,
+    );
+    loop {
+        game.frame();
+        if game.quit {
+            return;
         }
-
         next_frame().await
     }
 }
@@ -0,0 +1,222 @@
+/*
+ Platforms that travel along a path, carrying whatever stands on them. A
+ level's "moving_platforms" entry:
+
+ "moving_platforms": [
+     { "rect": [400, 380, 100, 20], "path": [[300, 0]], "speed": 80, "pause": 0.5 },
+     { "rect": [1200, 100, 60, 16], "one_way": true, "mode": "loop",
+       "path": [[120, 0], [120, -60], [0, -60]] }
+ ]
+
+ "rect" is where the platform starts and "path" the points it visits after
+ that, as offsets from the start. "ping_pong" (the default) goes to the end
+ of the path and back again, pausing "pause" seconds at both ends; "loop"
+ heads straight back to the start from the last point and pauses there.
+ "speed" is in pixels per second. The collision side (riding, pushing and
+ crushing) is in physics.rs.
+*/
+
+use macroquad::math::{Rect, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::level::{Platform, rect_array};
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathMode {
+    #[default]
+    PingPong,
+    Loop,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MovingPlatform {
+    #[serde(with = "rect_array")]
+    pub rect: Rect,
+    #[serde(default)]
+    pub one_way: bool,
+    pub path: Vec<Vec2>,
+    #[serde(default)]
+    pub mode: PathMode,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub pause: f32,
+    /// How far along the path it is now, and was before the last step.
+    #[serde(skip)]
+    pub offset: Vec2,
+    #[serde(skip)]
+    pub prev_offset: Vec2,
+    /// The point it's heading for: 0 is the start, 1 the first path point.
+    #[serde(skip)]
+    next: usize,
+    #[serde(skip)]
+    backwards: bool,
+    /// Seconds left to wait before moving on.
+    #[serde(skip)]
+    wait: f32,
+}
+
+fn default_speed() -> f32 {
+    80.0
+}
+
+impl MovingPlatform {
+    /// A ping-pong platform at `rect` that goes to `path` and back.
+    pub fn new(rect: Rect, path: Vec<Vec2>) -> Self {
+        MovingPlatform {
+            rect,
+            one_way: false,
+            path,
+            mode: PathMode::default(),
+            speed: default_speed(),
+            pause: 0.0,
+            offset: Vec2::ZERO,
+            prev_offset: Vec2::ZERO,
+            next: 0,
+            backwards: false,
+            wait: 0.0,
+        }
+    }
+
+    /// Moves it `dt` seconds further along its path.
+    pub fn update(&mut self, dt: f32) {
+        self.prev_offset = self.offset;
+        let mut left = self.wait_for(dt);
+        // A platform can pass several points in one step, but never more
+        // than the whole path (which could be all one point).
+        for _ in 0..2 * (self.path.len() + 1) {
+            if left <= 0.0 || self.path.is_empty() || self.speed <= 0.0 {
+                break;
+            }
+            let to = self.point(self.next) - self.offset;
+            let distance = to.length();
+            if self.speed * left < distance {
+                self.offset += to / distance * self.speed * left;
+                break;
+            }
+            self.offset = self.point(self.next);
+            left -= distance / self.speed;
+            self.arrive();
+            left = self.wait_for(left);
+        }
+    }
+
+    /// Where it is now.
+    pub fn rect(&self) -> Rect {
+        self.rect_at(self.offset)
+    }
+
+    /// Where it was before the last step.
+    pub fn prev_rect(&self) -> Rect {
+        self.rect_at(self.prev_offset)
+    }
+
+    pub fn rect_at(&self, offset: Vec2) -> Rect {
+        self.rect.offset(offset)
+    }
+
+    /// What it is for collision right now.
+    pub fn platform(&self) -> Platform {
+        Platform {
+            rect: self.rect(),
+            one_way: self.one_way,
+        }
+    }
+
+    /// Point `i` of the path, counting the start as point 0.
+    pub fn point(&self, i: usize) -> Vec2 {
+        if i == 0 { Vec2::ZERO } else { self.path[i - 1] }
+    }
+
+    /// Spends up to `dt` waiting and returns what's left of it.
+    fn wait_for(&mut self, dt: f32) -> f32 {
+        let waited = self.wait.min(dt);
+        self.wait -= waited;
+        dt - waited
+    }
+
+    /// Picks the next point after reaching `self.next`, and whether to
+    /// pause there first.
+    fn arrive(&mut self) {
+        let at = self.next;
+        let last = self.path.len();
+        match self.mode {
+            PathMode::PingPong => {
+                if at == last {
+                    self.backwards = true;
+                } else if at == 0 {
+                    self.backwards = false;
+                }
+                self.next = if self.backwards { at - 1 } else { at + 1 };
+                if at == 0 || at == last {
+                    self.wait = self.pause;
+                }
+            }
+            PathMode::Loop => {
+                self.next = (at + 1) % (last + 1);
+                if at == 0 {
+                    self.wait = self.pause;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::vec2;
+
+    use super::*;
+
+    /// Where `moving` is after each of `steps` seconds in turn.
+    fn offsets(mut moving: MovingPlatform, steps: &[f32]) -> Vec<Vec2> {
+        steps
+            .iter()
+            .map(|&dt| {
+                moving.update(dt);
+                moving.offset
+            })
+            .collect()
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn ping_pong_turns_and_pauses_at_both_ends() {
+        let mut moving =
+            MovingPlatform::new(Rect::new(0.0, 0.0, 50.0, 10.0), vec![vec2(100.0, 0.0)]);
+        moving.speed = 100.0;
+        moving.pause = 0.5;
+        // It waits at the start before setting off, too
+        let got = offsets(moving, &[1.5, 0.25, 0.5, 0.75, 0.25, 0.35]);
+        assert_near(got[0], vec2(100.0, 0.0));
+        assert_near(got[1], vec2(100.0, 0.0));
+        assert_near(got[2], vec2(75.0, 0.0));
+        assert_near(got[3], Vec2::ZERO);
+        assert_near(got[4], Vec2::ZERO);
+        assert_near(got[5], vec2(10.0, 0.0));
+    }
+
+    #[test]
+    fn loop_heads_straight_back_to_the_start() {
+        let path = vec![vec2(100.0, 0.0), vec2(100.0, -100.0)];
+        let mut moving = MovingPlatform::new(Rect::new(0.0, 0.0, 50.0, 10.0), path);
+        moving.mode = PathMode::Loop;
+        moving.speed = 100.0;
+        moving.pause = 0.5;
+        // Seconds to get from the last point straight back to the start
+        let back = std::f32::consts::SQRT_2;
+        let got = offsets(moving, &[2.5, 0.5, back - 0.5, 0.25, 0.5]);
+        // No pause at the far end...
+        assert_near(got[0], vec2(100.0, -100.0));
+        assert_near(got[1], vec2(100.0, -100.0) + vec2(-50.0, 50.0) / back);
+        // ...but one back at the start
+        assert_near(got[2], Vec2::ZERO);
+        assert_near(got[3], Vec2::ZERO);
+        assert_near(got[4], vec2(25.0, 0.0));
+    }
+}
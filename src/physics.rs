@@ -0,0 +1,390 @@
+/*
+ Collision between moving boxes and level geometry (platforms, tiles and
+ moving platforms).
+
+ Movement is resolved one axis at a time: first x against the sides of
+ solid platforms, then y against their tops and bottoms. One-way platforms
+ only ever stop something falling onto them from above.
+
+ Moving platforms move before anything else each step (see `carry`): boxes
+ standing on one go along with it, and solid ones shove boxes out of their
+ way. A box shoved into a wall is crushed.
+*/
+
+use macroquad::math::{Rect, Vec2, vec2};
+
+use crate::level::{Level, Platform};
+use crate::moving_platform::MovingPlatform;
+
+/// How close (in pixels) a box's feet must be to the top of a moving
+/// platform to ride it.
+const RIDE_TOLERANCE: f32 = 0.5;
+/// How far a box may be left overlapping a platform that shoved it before it
+/// counts as crushed.
+const CRUSH_TOLERANCE: f32 = 1.0;
+
+/// Everything boxes collide with.
+#[derive(Clone, Copy)]
+pub struct Solids<'a> {
+    pub level: &'a Level,
+    pub moving: &'a [MovingPlatform],
+    /// A moving platform to leave out, while it carries something.
+    pub except: Option<usize>,
+}
+
+impl<'a> Solids<'a> {
+    pub fn new(level: &'a Level, moving: &'a [MovingPlatform]) -> Self {
+        Solids {
+            level,
+            moving,
+            except: None,
+        }
+    }
+
+    /// Everything that might be collided with inside `area`.
+    pub fn near(&self, area: Rect) -> impl Iterator<Item = Platform> + 'a {
+        let except = self.except;
+        let moving = self
+            .moving
+            .iter()
+            .enumerate()
+            .filter(move |&(i, _)| Some(i) != except)
+            .map(|(_, moving)| moving.platform());
+        self.level.solids_near(area).chain(moving)
+    }
+}
+
+/// What a box bumped into during one move.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Contacts {
+    pub on_ground: bool,
+    pub hit_ceiling: bool,
+    pub hit_wall_left: bool,
+    pub hit_wall_right: bool,
+}
+
+impl Contacts {
+    pub fn hit_wall(&self) -> bool {
+        self.hit_wall_left || self.hit_wall_right
+    }
+}
+
+/// Like `Rect::overlaps`, but boxes that only touch don't count, so standing
+/// on a platform isn't mistaken for walking into it.
+pub fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.x < b.x + b.w && a.x + a.w > b.x && a.y < b.y + b.h && a.y + a.h > b.y
+}
+
+/// Whether there's anything to stand on at `point`, one-way platforms
+/// included.
+pub fn ground_at(point: Vec2, solids: &Solids) -> bool {
+    let probe = Rect::new(point.x, point.y, 0.0, 0.0);
+    solids.near(probe).any(|platform| {
+        let p = &platform.rect;
+        point.x >= p.x && point.x <= p.x + p.w && point.y >= p.y && point.y <= p.y + p.h
+    })
+}
+
+/// Whether nothing solid is in the way from `from` to `to`. One-way
+/// platforms can be seen through.
+pub fn line_of_sight(from: Vec2, to: Vec2, solids: &Solids) -> bool {
+    let area = Rect::new(from.x, from.y, 0.0, 0.0).combine_with(Rect::new(to.x, to.y, 0.0, 0.0));
+    !solids
+        .near(area)
+        .any(|platform| !platform.one_way && segment_hits(from, to, platform.rect))
+}
+
+/// Whether the line from `a` to `b` passes through the inside of `rect`
+/// (slab test: clip the line to the rect's x and then y range).
+fn segment_hits(a: Vec2, b: Vec2, rect: Rect) -> bool {
+    let (mut enter, mut exit) = (0.0f32, 1.0f32);
+    for (start, delta, low, high) in [
+        (a.x, b.x - a.x, rect.x, rect.x + rect.w),
+        (a.y, b.y - a.y, rect.y, rect.y + rect.h),
+    ] {
+        if delta == 0.0 {
+            if start <= low || start >= high {
+                return false;
+            }
+            continue;
+        }
+        let (t0, t1) = ((low - start) / delta, (high - start) / delta);
+        enter = enter.max(t0.min(t1));
+        exit = exit.min(t0.max(t1));
+        if enter >= exit {
+            return false;
+        }
+    }
+    true
+}
+
+/// Moves the `size` box at `pos` by `vel * dt`, stopping it at `solids`.
+/// The velocity component into anything hit is zeroed.
+pub fn move_and_collide(
+    pos: &mut Vec2,
+    vel: &mut Vec2,
+    size: Vec2,
+    dt: f32,
+    solids: &Solids,
+) -> Contacts {
+    let mut contacts = Contacts::default();
+    let at = |x: f32, y: f32| Rect::new(x, y, size.x, size.y);
+
+    // Horizontal: walls
+    let mut x = pos.x + vel.x * dt;
+    let swept = at(pos.x, pos.y).combine_with(at(x, pos.y));
+    for platform in solids.near(swept).filter(|p| !p.one_way) {
+        let p = &platform.rect;
+        if !overlaps(&at(x, pos.y), p) {
+            continue;
+        }
+        if vel.x > 0.0 && pos.x + size.x <= p.x {
+            x = p.x - size.x;
+            contacts.hit_wall_right = true;
+        } else if vel.x < 0.0 && pos.x >= p.x + p.w {
+            x = p.x + p.w;
+            contacts.hit_wall_left = true;
+        }
+    }
+    if contacts.hit_wall() {
+        vel.x = 0.0;
+    }
+    pos.x = x;
+
+    // Vertical: floors and ceilings
+    let mut y = pos.y + vel.y * dt;
+    let swept = at(pos.x, pos.y).combine_with(at(pos.x, y));
+    for platform in solids.near(swept) {
+        let p = &platform.rect;
+        if !overlaps(&at(pos.x, y), p) {
+            continue;
+        }
+        if vel.y > 0.0 && pos.y + size.y <= p.y {
+            y = p.y - size.y;
+            contacts.on_ground = true;
+        } else if vel.y < 0.0 && !platform.one_way && pos.y >= p.y + p.h {
+            y = p.y + p.h;
+            contacts.hit_ceiling = true;
+        }
+    }
+    if contacts.on_ground || contacts.hit_ceiling {
+        vel.y = 0.0;
+    }
+    pos.y = y;
+
+    contacts
+}
+
+/// Moves the `size` box at `pos` (falling at `vel_y`) along with moving
+/// platform `index` of `solids`, which was at `before` until this step:
+/// a box standing on it rides along and a box in the way of a solid one is
+/// pushed out. Returns how far the box moved, or `None` if it was crushed
+/// between the platform and something else.
+pub fn carry(
+    pos: &mut Vec2,
+    vel_y: f32,
+    size: Vec2,
+    before: Rect,
+    index: usize,
+    solids: &Solids,
+) -> Option<Vec2> {
+    let platform = solids.moving[index].platform();
+    let after = platform.rect;
+    let rect = Rect::new(pos.x, pos.y, size.x, size.y);
+
+    let riding = vel_y >= 0.0
+        && (rect.bottom() - before.top()).abs() <= RIDE_TOLERANCE
+        && rect.right() > before.left()
+        && rect.left() < before.right();
+    let mut push = if riding {
+        vec2(after.x - before.x, after.top() - rect.bottom())
+    } else if !platform.one_way && overlaps(&rect, &after) {
+        push_out(rect, after, after.point() - before.point())
+    } else {
+        return Some(Vec2::ZERO);
+    };
+
+    let start = *pos;
+    let others = Solids {
+        except: Some(index),
+        ..*solids
+    };
+    move_and_collide(pos, &mut push, size, 1.0, &others);
+
+    let inset = Rect::new(
+        pos.x + CRUSH_TOLERANCE,
+        pos.y + CRUSH_TOLERANCE,
+        size.x - 2.0 * CRUSH_TOLERANCE,
+        size.y - 2.0 * CRUSH_TOLERANCE,
+    );
+    let crushed = !platform.one_way && overlaps(&inset, &after);
+    (!crushed).then_some(*pos - start)
+}
+
+/// The shortest move, in a direction the platform went by `delta`, that
+/// takes `rect` out of the platform now at `platform`.
+fn push_out(rect: Rect, platform: Rect, delta: Vec2) -> Vec2 {
+    let mut moves = Vec::new();
+    if delta.x > 0.0 {
+        moves.push(vec2(platform.right() - rect.left(), 0.0));
+    }
+    if delta.x < 0.0 {
+        moves.push(vec2(platform.left() - rect.right(), 0.0));
+    }
+    if delta.y > 0.0 {
+        moves.push(vec2(0.0, platform.bottom() - rect.top()));
+    }
+    if delta.y < 0.0 {
+        moves.push(vec2(0.0, platform.top() - rect.bottom()));
+    }
+    moves
+        .into_iter()
+        .min_by(|a, b| a.length().total_cmp(&b.length()))
+        .unwrap_or(Vec2::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Vec2 = vec2(20.0, 20.0);
+
+    /// A floor, a wall standing on it, a ceiling and a one-way ledge.
+    fn level() -> Level {
+        serde_json::from_str(
+            r#"{
+                "platforms": [
+                    [0, 400, 1000, 40],
+                    [500, 300, 40, 100],
+                    [0, 200, 300, 20],
+                    { "rect": [600, 300, 100, 10], "one_way": true }
+                ],
+                "start": [0, 0],
+                "goal_x": 1000
+            }"#,
+        )
+        .unwrap()
+    }
+
+    /// Moves a box from `pos` at `vel` for 0.1 s.
+    fn step(pos: Vec2, vel: Vec2) -> (Vec2, Vec2, Contacts) {
+        let level = level();
+        let (mut pos, mut vel) = (pos, vel);
+        let contacts = move_and_collide(&mut pos, &mut vel, SIZE, 0.1, &Solids::new(&level, &[]));
+        (pos, vel, contacts)
+    }
+
+    #[test]
+    fn lands_on_the_floor() {
+        let (pos, vel, contacts) = step(vec2(100.0, 370.0), vec2(0.0, 600.0));
+        assert_eq!(pos, vec2(100.0, 380.0));
+        assert_eq!(vel, Vec2::ZERO);
+        assert!(contacts.on_ground && !contacts.hit_ceiling && !contacts.hit_wall());
+    }
+
+    #[test]
+    fn stops_at_walls() {
+        let (pos, vel, contacts) = step(vec2(470.0, 370.0), vec2(400.0, 0.0));
+        assert_eq!(pos.x, 480.0);
+        assert_eq!(vel.x, 0.0);
+        assert!(contacts.hit_wall_right && !contacts.hit_wall_left);
+
+        let (pos, _, contacts) = step(vec2(550.0, 370.0), vec2(-400.0, 0.0));
+        assert_eq!(pos.x, 540.0);
+        assert!(contacts.hit_wall_left && !contacts.hit_wall_right);
+    }
+
+    #[test]
+    fn bumps_into_ceilings() {
+        let (pos, vel, contacts) = step(vec2(100.0, 230.0), vec2(50.0, -400.0));
+        assert_eq!(pos, vec2(105.0, 220.0));
+        assert_eq!(vel, vec2(50.0, 0.0));
+        assert!(contacts.hit_ceiling && !contacts.on_ground);
+    }
+
+    #[test]
+    fn one_way_platforms_only_stop_falls() {
+        // Up through it from below
+        let (pos, _, contacts) = step(vec2(620.0, 330.0), vec2(0.0, -400.0));
+        assert_eq!(pos.y, 290.0);
+        assert!(!contacts.hit_ceiling);
+        // Sideways into it
+        let (pos, _, contacts) = step(vec2(570.0, 295.0), vec2(400.0, 0.0));
+        assert_eq!(pos.x, 610.0);
+        assert!(!contacts.hit_wall());
+        // Onto it from above
+        let (pos, _, contacts) = step(vec2(620.0, 270.0), vec2(0.0, 200.0));
+        assert_eq!(pos.y, 280.0);
+        assert!(contacts.on_ground);
+    }
+
+    /// Moves `moving` on by 0.5 s and carries a box at `pos` along with it.
+    fn carried(moving: MovingPlatform, pos: Vec2) -> (Vec2, Option<Vec2>) {
+        let level = level();
+        let mut moving = [moving];
+        moving[0].update(0.5);
+        let mut pos = pos;
+        let before = moving[0].prev_rect();
+        let moved = carry(
+            &mut pos,
+            0.0,
+            SIZE,
+            before,
+            0,
+            &Solids::new(&level, &moving),
+        );
+        (pos, moved)
+    }
+
+    fn platform(path: Vec2) -> MovingPlatform {
+        // Under the ceiling, clear of everything else; 80 px/s
+        MovingPlatform::new(Rect::new(100.0, 300.0, 100.0, 20.0), vec![path])
+    }
+
+    #[test]
+    fn riders_go_along() {
+        let (pos, moved) = carried(platform(vec2(100.0, 0.0)), vec2(120.0, 280.0));
+        assert_eq!(pos, vec2(160.0, 280.0));
+        assert_eq!(moved, Some(vec2(40.0, 0.0)));
+
+        let (pos, _) = carried(platform(vec2(0.0, 50.0)), vec2(120.0, 280.0));
+        assert_eq!(pos, vec2(120.0, 320.0));
+
+        // Not standing on it: left behind
+        let (pos, moved) = carried(platform(vec2(100.0, 0.0)), vec2(120.0, 250.0));
+        assert_eq!(pos, vec2(120.0, 250.0));
+        assert_eq!(moved, Some(Vec2::ZERO));
+    }
+
+    #[test]
+    fn boxes_in_the_way_are_pushed() {
+        let (pos, moved) = carried(platform(vec2(100.0, 0.0)), vec2(205.0, 300.0));
+        assert_eq!(pos, vec2(240.0, 300.0));
+        assert_eq!(moved, Some(vec2(35.0, 0.0)));
+
+        // One-way platforms pass through instead
+        let mut one_way = platform(vec2(100.0, 0.0));
+        one_way.one_way = true;
+        let (pos, moved) = carried(one_way, vec2(205.0, 300.0));
+        assert_eq!(pos, vec2(205.0, 300.0));
+        assert_eq!(moved, Some(Vec2::ZERO));
+    }
+
+    #[test]
+    fn boxes_pushed_into_walls_are_crushed() {
+        // Up into the ceiling
+        let moving = MovingPlatform::new(
+            Rect::new(100.0, 260.0, 100.0, 20.0),
+            vec![vec2(0.0, -100.0)],
+        );
+        let (pos, moved) = carried(moving, vec2(120.0, 240.0));
+        assert_eq!(pos.y, 220.0);
+        assert_eq!(moved, None);
+        // Sideways into the wall at x = 500
+        let moving =
+            MovingPlatform::new(Rect::new(350.0, 330.0, 100.0, 20.0), vec![vec2(100.0, 0.0)]);
+        let (pos, moved) = carried(moving, vec2(470.0, 330.0));
+        assert_eq!(pos.x, 480.0);
+        assert_eq!(moved, None);
+    }
+}
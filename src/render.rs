@@ -0,0 +1,546 @@
+/*
+ Everything that draws with macroquad. The simulation types don't know how
+ they look; their draw() methods live here. Each uses its sprite when the
+ atlas has one and falls back to primitive shapes otherwise.
+*/
+
+use macroquad::prelude::*;
+
+use crate::ai::AiState;
+use crate::animation::{AnimEvent, AnimState, Animator, Animators};
+use crate::entities::{Bonus, Bullet, Checkpoint, Enemy, Player, PowerUp, PowerUpType, Team};
+use crate::level::{Hazard, HazardKind, Level};
+use crate::moving_platform::MovingPlatform;
+use crate::sprites::Sprites;
+use crate::tilemap::{Tile, TileMap};
+use crate::weapon::WeaponKind;
+use crate::world::World;
+use crate::{BONUS_SIZE, PLAYER_HEIGHT, PLAYER_WIDTH, POWERUP_SIZE};
+
+/// How many times a second the player switches between shown and hidden
+/// while invulnerable.
+const FLASH_RATE: f32 = 10.0;
+
+impl PowerUp {
+    pub fn draw(&self, sprites: &Sprites, camera: Vec2) {
+        if !self.collected {
+            let (sprite, color) = match self.kind {
+                PowerUpType::Health => ("powerup_health", PINK),
+                PowerUpType::Speed => ("powerup_speed", ORANGE),
+                PowerUpType::Invincibility => ("powerup_invincibility", PURPLE),
+                PowerUpType::HighJump => ("powerup_high_jump", BLUE), // NEW
+                PowerUpType::SpreadShot => ("powerup_spread_shot", DARKGREEN),
+                PowerUpType::RapidFire => ("powerup_rapid_fire", RED),
+                PowerUpType::Laser => ("powerup_laser", SKYBLUE),
+            };
+            let dest = Rect::new(
+                self.pos.x - camera.x,
+                self.pos.y - camera.y,
+                POWERUP_SIZE,
+                POWERUP_SIZE,
+            );
+            if sprites.draw(sprite, dest, false, WHITE) {
+                return;
+            }
+            draw_circle(
+                self.pos.x - camera.x + POWERUP_SIZE / 2.0,
+                self.pos.y - camera.y + POWERUP_SIZE / 2.0,
+                POWERUP_SIZE / 2.0,
+                color,
+            );
+        }
+    }
+}
+
+impl Enemy {
+    pub fn draw(&self, sprites: &Sprites, animator: &Animator, camera: Vec2, alpha: f32) {
+        if self.alive {
+            let pos = self.prev_pos.lerp(self.pos, alpha);
+            let x = pos.x - camera.x;
+            let y = pos.y - camera.y;
+            let sprite = animated_frame(sprites, animator, self.sprite_name());
+            let size = self.archetype.size;
+            let dest = Rect::new(x, y, size.x, size.y);
+            // What it's thinking, over its head
+            let mark = match self.state {
+                AiState::Alert => Some(("!", YELLOW)),
+                AiState::Return => Some(("?", LIGHTGRAY)),
+                _ => None,
+            };
+            if let Some((mark, color)) = mark {
+                draw_text(mark, x + size.x / 2.0 - 4.0, y - 6.0, 28.0, color);
+            }
+            if sprites.draw(sprite, dest, !self.facing_right, WHITE) {
+                return;
+            }
+            // A stick figure, scaled from one 45 high
+            let (w, h) = (size.x, size.y);
+            let s = h / 45.0;
+            let mid = x + w / 2.0;
+            let stompable = self.archetype.vulnerable_to.to_stomp();
+            let color = if self.archetype.health > 1 {
+                DARKGRAY
+            } else {
+                RED
+            };
+            // Head
+            let head_color = if stompable { LIME } else { RED };
+            draw_circle(mid, y + 12.0 * s, 10.0 * s, head_color);
+            // "Hat" for jump-on enemies
+            if stompable {
+                draw_rectangle(mid - 8.0 * s, y + 2.0 * s, 16.0 * s, 4.0 * s, DARKGREEN);
+            }
+            // Body
+            draw_rectangle(mid - 5.0 * s, y + 22.0 * s, 10.0 * s, 14.0 * s, color);
+            // Gun for shooters
+            if self.archetype.shooter.is_some() {
+                draw_rectangle(mid - 8.0 * s, y + 26.0 * s, 16.0 * s, 4.0 * s, DARKGRAY);
+            }
+            // Arms
+            draw_line(mid, y + 24.0 * s, x, y + 28.0 * s, 2.0, color);
+            draw_line(mid, y + 24.0 * s, x + w, y + 28.0 * s, 2.0, color);
+            // Legs
+            draw_line(mid, y + 36.0 * s, x + 4.0 * s, y + h, 2.0, color);
+            draw_line(mid, y + 36.0 * s, x + w - 4.0 * s, y + h, 2.0, color);
+        }
+    }
+}
+
+impl Bonus {
+    pub fn draw(&self, sprites: &Sprites, camera: Vec2) {
+        if !self.collected {
+            let at = self.pos - camera;
+            let dest = Rect::new(at.x, at.y, BONUS_SIZE, BONUS_SIZE);
+            if sprites.draw("bonus", dest, false, WHITE) {
+                return;
+            }
+            draw_circle(
+                at.x + BONUS_SIZE / 2.0,
+                at.y + BONUS_SIZE / 2.0,
+                BONUS_SIZE / 2.0,
+                GOLD,
+            );
+        }
+    }
+}
+
+impl Checkpoint {
+    /// A flag pole at the checkpoint's left edge, with the flag up once it's
+    /// been reached and lit while it's the one the player would go back to.
+    pub fn draw(&self, sprites: &Sprites, camera: Vec2, active: bool) {
+        let r = self.rect();
+        let dest = Rect::new(r.x - camera.x, r.y - camera.y, r.w, r.h);
+        let sprite = match (self.reached, active) {
+            (_, true) => "checkpoint_active",
+            (true, false) => "checkpoint_reached",
+            (false, false) => "checkpoint",
+        };
+        if sprites.draw(sprite, dest, false, WHITE) {
+            return;
+        }
+        draw_line(dest.x, dest.y, dest.x, dest.bottom(), 3.0, DARKGRAY);
+        let flag_y = if self.reached {
+            dest.y
+        } else {
+            dest.y + dest.h * 0.6
+        };
+        let color = if active { GOLD } else { LIGHTGRAY };
+        draw_triangle(
+            vec2(dest.x, flag_y),
+            vec2(dest.x + dest.w * 0.7, flag_y + 8.0),
+            vec2(dest.x, flag_y + 16.0),
+            color,
+        );
+    }
+}
+
+impl Bullet {
+    pub fn draw(&self, sprites: &Sprites, camera: Vec2, alpha: f32) {
+        if self.alive {
+            let pos = self.prev_pos.lerp(self.pos, alpha);
+            let dest = Rect::new(pos.x - camera.x, pos.y - camera.y, 10.0, 4.0);
+            let (sprite, color) = match (self.team, self.weapon) {
+                (Team::Enemies, _) => ("bullet_enemy", ORANGE),
+                (Team::Player, WeaponKind::Laser) => ("bullet_laser", SKYBLUE),
+                (Team::Player, _) => ("bullet", YELLOW),
+            };
+            if sprites.draw(sprite, dest, self.vel.x < 0.0, WHITE) {
+                return;
+            }
+            draw_rectangle(dest.x, dest.y, dest.w, dest.h, color);
+        }
+    }
+}
+
+impl Player {
+    pub fn draw(&self, sprites: &Sprites, animator: &Animator, camera: Vec2, alpha: f32) {
+        // Flashes while invulnerable after a hit
+        if (self.invulnerable_timer * FLASH_RATE) as i32 % 2 == 1 {
+            return;
+        }
+        let pos = self.prev_pos.lerp(self.pos, alpha);
+        let x = pos.x - camera.x;
+        let y = pos.y - camera.y;
+        let dest = Rect::new(x, y, PLAYER_WIDTH, PLAYER_HEIGHT);
+        let tint = if !self.alive {
+            GRAY
+        } else if self.invincible_timer > 0.0 {
+            YELLOW
+        } else {
+            WHITE
+        };
+        let sprite = animated_frame(sprites, animator, "player");
+        if sprites.draw(sprite, dest, !self.facing_right, tint) {
+            return;
+        }
+        let state = self.anim_state();
+        if state == AnimState::Dead {
+            // Flat on the ground
+            let ground = y + PLAYER_HEIGHT;
+            draw_circle(x + 10.0, ground - 10.0, 10.0, GRAY);
+            draw_rectangle(x + 20.0, ground - 9.0, 14.0, 8.0, GRAY);
+            draw_line(
+                x + 34.0,
+                ground - 5.0,
+                x + PLAYER_WIDTH + 10.0,
+                ground - 2.0,
+                3.0,
+                GRAY,
+            );
+            draw_line(
+                x + 34.0,
+                ground - 5.0,
+                x + PLAYER_WIDTH + 8.0,
+                ground - 9.0,
+                3.0,
+                GRAY,
+            );
+            return;
+        }
+
+        let body_color = if self.invincible_timer > 0.0 {
+            YELLOW
+        } else {
+            BLUE
+        };
+        // The pose faces right; `ahead` mirrors it when facing left
+        let cx = x + PLAYER_WIDTH / 2.0;
+        let ahead = if self.facing_right { 1.0 } else { -1.0 };
+        let at = |dx: f32, dy: f32| vec2(cx + dx * ahead, y + dy);
+        // Legs and arms swing with the distance covered
+        let swing = |stride: f32| (pos.x / stride).sin();
+        let (head, arms, legs) = match state {
+            AnimState::Walk => {
+                let s = swing(10.0);
+                (
+                    at(0.0, 14.0),
+                    [at(-8.0 * s, 38.0), at(8.0 * s, 38.0)],
+                    [at(10.0 * s, 50.0), at(-10.0 * s, 50.0)],
+                )
+            }
+            AnimState::Run => {
+                let s = swing(14.0);
+                (
+                    at(3.0, 15.0),
+                    [at(-14.0 * s, 34.0), at(14.0 * s, 34.0)],
+                    [at(15.0 * s, 49.0), at(-15.0 * s, 49.0)],
+                )
+            }
+            // Arms up, knees tucked
+            AnimState::Jump => (
+                at(0.0, 14.0),
+                [at(-12.0, 14.0), at(12.0, 14.0)],
+                [at(-7.0, 46.0), at(9.0, 42.0)],
+            ),
+            // Arms out for balance, legs dangling
+            AnimState::Fall => (
+                at(0.0, 14.0),
+                [at(-20.0, 24.0), at(20.0, 24.0)],
+                [at(-4.0, 50.0), at(4.0, 50.0)],
+            ),
+            // One arm pointing ahead
+            AnimState::Shoot => (
+                at(0.0, 14.0),
+                [at(-10.0, 37.0), at(22.0, 29.0)],
+                [at(-13.0, 50.0), at(13.0, 50.0)],
+            ),
+            // Thrown back, arms flung up
+            AnimState::Hurt => (
+                at(-4.0, 15.0),
+                [at(-16.0, 16.0), at(-6.0, 12.0)],
+                [at(-10.0, 50.0), at(10.0, 50.0)],
+            ),
+            _ => (
+                at(0.0, 14.0),
+                [at(-18.0, 35.0), at(18.0, 35.0)],
+                [at(-13.0, 50.0), at(13.0, 50.0)],
+            ),
+        };
+        let head_color = if state == AnimState::Hurt {
+            RED
+        } else {
+            YELLOW
+        };
+        draw_circle(head.x, head.y, 12.0, head_color);
+        draw_rectangle(cx - 5.0, y + 26.0, 10.0, 16.0, body_color);
+        for hand in arms {
+            draw_line(cx, y + 28.0, hand.x, hand.y, 3.0, body_color);
+        }
+        for foot in legs {
+            draw_line(cx, y + 42.0, foot.x, foot.y, 3.0, body_color);
+        }
+    }
+}
+
+impl TileMap {
+    /// Draws the tiles that are on screen.
+    pub fn draw(&self, sprites: &Sprites, camera: Vec2) {
+        let view = Rect::new(camera.x, camera.y, screen_width(), screen_height());
+        for (rect, tile) in self.tiles_in(view) {
+            let dest = Rect::new(rect.x - camera.x, rect.y - camera.y, rect.w, rect.h);
+            let sprite = match tile {
+                Tile::Empty => continue,
+                Tile::Solid => "tile_solid",
+                Tile::OneWay => "tile_one_way",
+                Tile::Hazard => "tile_hazard",
+                Tile::Decoration => "tile_decoration",
+            };
+            if sprites.draw(sprite, dest, false, WHITE) {
+                continue;
+            }
+            match tile {
+                Tile::Solid => draw_rectangle(dest.x, dest.y, dest.w, dest.h, DARKGREEN),
+                Tile::OneWay => draw_rectangle(dest.x, dest.y, dest.w, dest.h / 4.0, GREEN),
+                Tile::Hazard => {
+                    let spikes =
+                        Rect::new(dest.x, dest.y + dest.h / 3.0, dest.w, dest.h * 2.0 / 3.0);
+                    draw_spikes(spikes, 3);
+                }
+                _ => draw_rectangle(
+                    dest.x,
+                    dest.y,
+                    dest.w,
+                    dest.h,
+                    Color::new(0.2, 0.5, 0.2, 0.35),
+                ),
+            }
+        }
+    }
+}
+
+impl Hazard {
+    /// Draws spikes and lava. Pits are left for the level around them to
+    /// show.
+    pub fn draw(&self, sprites: &Sprites, camera: Vec2) {
+        let r = self.rect;
+        let dest = Rect::new(r.x - camera.x, r.y - camera.y, r.w, r.h);
+        let sprite = match self.kind {
+            HazardKind::Spikes => "hazard_spikes",
+            HazardKind::Lava => "hazard_lava",
+            HazardKind::Pit => return,
+        };
+        if sprites.draw(sprite, dest, false, WHITE) {
+            return;
+        }
+        if self.kind == HazardKind::Spikes {
+            draw_spikes(dest, (dest.w / 13.0).round().max(1.0) as usize);
+        } else {
+            draw_rectangle(dest.x, dest.y, dest.w, dest.h, ORANGE);
+            draw_rectangle(dest.x, dest.y, dest.w, dest.h.min(4.0), GOLD);
+        }
+    }
+}
+
+/// A row of `count` spikes filling `dest`.
+fn draw_spikes(dest: Rect, count: usize) {
+    let spike = dest.w / count as f32;
+    for i in 0..count {
+        let x = dest.x + i as f32 * spike;
+        draw_triangle(
+            vec2(x, dest.bottom()),
+            vec2(x + spike / 2.0, dest.y),
+            vec2(x + spike, dest.bottom()),
+            RED,
+        );
+    }
+}
+
+impl MovingPlatform {
+    pub fn draw(&self, sprites: &Sprites, camera: Vec2, alpha: f32) {
+        let offset = self.prev_offset.lerp(self.offset, alpha);
+        let r = self.rect_at(offset);
+        let dest = Rect::new(r.x - camera.x, r.y - camera.y, r.w, r.h);
+        let sprite = if self.one_way {
+            "moving_platform_one_way"
+        } else {
+            "moving_platform"
+        };
+        if sprites.draw(sprite, dest, false, WHITE) {
+            return;
+        }
+        if self.one_way {
+            draw_rectangle(dest.x, dest.y, dest.w, dest.h / 2.0, GOLD);
+        } else {
+            draw_rectangle(dest.x, dest.y, dest.w, dest.h, BROWN);
+            draw_rectangle_lines(dest.x, dest.y, dest.w, dest.h, 2.0, GOLD);
+        }
+    }
+}
+
+/// The frame `animator` is on in the animations for `name`, or the still
+/// sprite `name` if there are none.
+fn animated_frame<'a>(sprites: &'a Sprites, animator: &Animator, name: &'a str) -> &'a str {
+    sprites
+        .animations(name)
+        .and_then(|set| animator.frame(set))
+        .unwrap_or(name)
+}
+
+/// Short-lived decorations spawned by animation events: "dust" (a few puffs
+/// kicked up at someone's feet) and "impact" (sparks where a bullet hit).
+#[derive(Default)]
+pub struct Effects {
+    puffs: Vec<Puff>,
+}
+
+struct Puff {
+    pos: Vec2,
+    vel: Vec2,
+    age: f32,
+    color: Color,
+}
+
+const PUFF_LIFETIME: f32 = 0.4;
+
+impl Effects {
+    pub fn spawn(&mut self, event: &AnimEvent) {
+        match event.name.as_str() {
+            "dust" => {
+                for dir in [-1.0, -0.4, 0.4, 1.0] {
+                    self.puffs.push(Puff {
+                        pos: event.pos,
+                        vel: vec2(dir * 60.0, -20.0),
+                        age: 0.0,
+                        color: Color::new(0.5, 0.5, 0.5, 0.6),
+                    });
+                }
+            }
+            "impact" => {
+                for dir in [
+                    vec2(-1.0, -1.0),
+                    vec2(1.0, -1.0),
+                    vec2(-0.5, 0.6),
+                    vec2(0.5, 0.6),
+                ] {
+                    self.puffs.push(Puff {
+                        // Puffs are drawn 3 above where they are
+                        pos: event.pos + vec2(0.0, 3.0),
+                        vel: dir * 90.0,
+                        age: 0.0,
+                        color: Color::new(1.0, 0.8, 0.3, 0.9),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for puff in &mut self.puffs {
+            puff.pos += puff.vel * dt;
+            puff.age += dt;
+        }
+        self.puffs.retain(|p| p.age < PUFF_LIFETIME);
+    }
+
+    pub fn clear(&mut self) {
+        self.puffs.clear();
+    }
+
+    pub fn draw(&self, camera: Vec2) {
+        for puff in &self.puffs {
+            let t = puff.age / PUFF_LIFETIME;
+            let color = Color {
+                a: puff.color.a * (1.0 - t),
+                ..puff.color
+            };
+            draw_circle(
+                puff.pos.x - camera.x,
+                puff.pos.y - camera.y - 3.0,
+                3.0 + 4.0 * t,
+                color,
+            );
+        }
+    }
+}
+
+/// Level geometry and everything in it, seen from `camera` (the top left of the view). Moving
+/// things are drawn `alpha` of the way from their previous to their current
+/// position, so motion stays smooth between fixed simulation steps.
+pub fn draw_world(
+    world: &World,
+    sprites: &Sprites,
+    animators: &Animators,
+    effects: &Effects,
+    camera: Vec2,
+    alpha: f32,
+) {
+    draw_geometry(world.level(), sprites, camera);
+    for moving in &world.moving_platforms {
+        moving.draw(sprites, camera, alpha);
+    }
+    let still = Animator::default();
+    for (i, enemy) in world.enemies.iter().enumerate() {
+        let animator = animators.enemies.get(i).unwrap_or(&still);
+        enemy.draw(sprites, animator, camera, alpha);
+    }
+    for (i, checkpoint) in world.checkpoints.iter().enumerate() {
+        checkpoint.draw(sprites, camera, world.is_active_checkpoint(i));
+    }
+    for bonus in &world.bonuses {
+        bonus.draw(sprites, camera);
+    }
+    for powerup in &world.powerups {
+        powerup.draw(sprites, camera);
+    }
+    world.player.draw(sprites, &animators.player, camera, alpha);
+    effects.draw(camera);
+    for bullet in &world.bullets {
+        bullet.draw(sprites, camera, alpha);
+    }
+}
+
+/// A level as it is before anything has moved, for the editor.
+pub fn draw_level(level: &Level, sprites: &Sprites, camera: Vec2) {
+    draw_geometry(level, sprites, camera);
+    for moving in &level.moving_platforms {
+        moving.draw(sprites, camera, 1.0);
+    }
+    let still = Animator::default();
+    for enemy in &level.enemies {
+        enemy.draw(sprites, &still, camera, 1.0);
+    }
+    for checkpoint in &level.checkpoints {
+        checkpoint.draw(sprites, camera, false);
+    }
+    for bonus in &level.bonuses {
+        bonus.draw(sprites, camera);
+    }
+    for powerup in &level.powerups {
+        powerup.draw(sprites, camera);
+    }
+}
+
+fn draw_geometry(level: &Level, sprites: &Sprites, camera: Vec2) {
+    if let Some(tiles) = &level.tiles {
+        tiles.draw(sprites, camera);
+    }
+    for platform in &level.platforms {
+        let r = platform.rect;
+        let color = if platform.one_way { GREEN } else { DARKGREEN };
+        draw_rectangle(r.x - camera.x, r.y - camera.y, r.w, r.h, color);
+    }
+    for hazard in &level.hazards {
+        hazard.draw(sprites, camera);
+    }
+}
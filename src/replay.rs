@@ -0,0 +1,327 @@
+/*
+ Replays: the input of every simulation step of one run, plus what's needed
+ to start the run over (seed, difficulty, level, step size, the player's
+ health and score). Playing the inputs back through World::step reproduces the run
+ exactly on the same build, and the state hash stored at the end shows
+ whether it did.
+
+ File layout, all little-endian:
+
+   "ADVR"        magic
+   u8            format version (2)
+   u64           seed
+   u8            difficulty (0 easy, 1 normal, 2 hard)
+   u32           level index
+   f32           step size in seconds
+   i32, i32      player health, score
+   u32           number of runs, then per run:
+     u16         InputFrame bits
+     u32         how many steps in a row had that input
+   u64           World::state_hash() after the last step
+*/
+
+use std::fs;
+use std::path::Path;
+
+use crate::data::DataError;
+use crate::world::{Difficulty, InputFrame, World};
+
+pub const LAST_REPLAY_FILE: &str = "last.replay";
+
+const MAGIC: &[u8; 4] = b"ADVR";
+const VERSION: u8 = 2;
+/// The longest replay that will be loaded: eight hours at 120 steps a
+/// second. Anything claiming more is broken, and expanding it would run out
+/// of memory.
+const MAX_STEPS: u64 = 8 * 60 * 60 * 120;
+
+pub struct Replay {
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub level: usize,
+    pub step_dt: f32,
+    pub health: i32,
+    pub score: i32,
+    pub inputs: Vec<InputFrame>,
+    pub final_hash: u64,
+}
+
+impl Replay {
+    /// An empty recording of a run starting from `world` as it is now.
+    pub fn start(world: &World, step_dt: f32) -> Self {
+        Replay {
+            seed: world.seed,
+            difficulty: world.difficulty,
+            level: world.current_level,
+            step_dt,
+            health: world.player.health,
+            score: world.player.score,
+            inputs: Vec::new(),
+            final_hash: world.state_hash(),
+        }
+    }
+
+    /// Adds one step's input; `world` is the state after that step.
+    pub fn record(&mut self, input: InputFrame, world: &World) {
+        self.inputs.push(input);
+        self.final_hash = world.state_hash();
+    }
+
+    /// Sets `world` up the way it was when the recording started.
+    pub fn setup(&self, world: &mut World) -> Result<(), String> {
+        if self.level >= world.levels.len() {
+            return Err(format!(
+                "the replay starts on level {} but there are only {} levels",
+                self.level + 1,
+                world.levels.len()
+            ));
+        }
+        world.seed = self.seed;
+        world.difficulty = self.difficulty;
+        world.start_run(self.level);
+        world.carry_over(self.score, self.health);
+        Ok(())
+    }
+
+    pub fn save(&self, file: &Path) -> Result<(), DataError> {
+        fs::write(file, self.encode()).map_err(|e| DataError::new(file, e.to_string()))
+    }
+
+    pub fn load(file: &Path) -> Result<Self, DataError> {
+        let bytes = fs::read(file).map_err(|e| DataError::new(file, e.to_string()))?;
+        Replay::decode(&bytes).map_err(|message| DataError::new(file, message))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut runs: Vec<(u16, u32)> = Vec::new();
+        for input in &self.inputs {
+            let bits = input.to_bits();
+            match runs.last_mut() {
+                Some((last, count)) if *last == bits && *count < u32::MAX => *count += 1,
+                _ => runs.push((bits, 1)),
+            }
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.push(self.difficulty.to_byte());
+        out.extend_from_slice(&(self.level as u32).to_le_bytes());
+        out.extend_from_slice(&self.step_dt.to_le_bytes());
+        out.extend_from_slice(&self.health.to_le_bytes());
+        out.extend_from_slice(&self.score.to_le_bytes());
+        out.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (bits, count) in runs {
+            out.extend_from_slice(&bits.to_le_bytes());
+            out.extend_from_slice(&count.to_le_bytes());
+        }
+        out.extend_from_slice(&self.final_hash.to_le_bytes());
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(4)? != MAGIC {
+            return Err("not a replay file".to_string());
+        }
+        let version = r.take(1)?[0];
+        if version != VERSION {
+            return Err(format!("unsupported replay version {}", version));
+        }
+        let seed = u64::from_le_bytes(r.array()?);
+        let byte = r.take(1)?[0];
+        let difficulty =
+            Difficulty::from_byte(byte).ok_or_else(|| format!("unknown difficulty {}", byte))?;
+        let level = u32::from_le_bytes(r.array()?) as usize;
+        let step_dt = f32::from_le_bytes(r.array()?);
+        if !step_dt.is_finite() || step_dt <= 0.0 {
+            return Err(format!("bad step size {}", step_dt));
+        }
+        let health = i32::from_le_bytes(r.array()?);
+        if health <= 0 {
+            return Err(format!("bad starting health {}", health));
+        }
+        let score = i32::from_le_bytes(r.array()?);
+        let run_count = u32::from_le_bytes(r.array()?);
+        let mut runs = Vec::new();
+        let mut steps = 0u64;
+        for _ in 0..run_count {
+            let input = InputFrame::from_bits(u16::from_le_bytes(r.array()?));
+            let count = u32::from_le_bytes(r.array()?);
+            steps += count as u64;
+            if steps > MAX_STEPS {
+                return Err(format!("replay longer than {} steps", MAX_STEPS));
+            }
+            runs.push((input, count));
+        }
+        let mut inputs = Vec::with_capacity(steps as usize);
+        for (input, count) in runs {
+            inputs.extend(std::iter::repeat_n(input, count as usize));
+        }
+        let final_hash = u64::from_le_bytes(r.array()?);
+        if r.pos != bytes.len() {
+            return Err("unexpected data after the end of the replay".to_string());
+        }
+        Ok(Replay {
+            seed,
+            difficulty,
+            level,
+            step_dt,
+            health,
+            score,
+            inputs,
+            final_hash,
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + n;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or_else(|| format!("replay cut short at byte {}", self.pos))?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+/// Feeds a replay's inputs out one step at a time.
+pub struct Playback {
+    pub replay: Replay,
+    next: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback { replay, next: 0 }
+    }
+
+    /// Starts over from the first step, resetting `world` to match.
+    pub fn rewind(&mut self, world: &mut World) -> Result<(), String> {
+        self.next = 0;
+        self.replay.setup(world)
+    }
+
+    pub fn next_input(&mut self) -> Option<InputFrame> {
+        let input = self.replay.inputs.get(self.next).copied();
+        self.next += input.is_some() as usize;
+        input
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.replay.inputs.len()
+    }
+
+    /// Whether `world`, after the last step, is where the recording ended.
+    pub fn matches(&self, world: &World) -> bool {
+        world.state_hash() == self.replay.final_hash
+    }
+}
+
+/// Plays `replay` back on a world without a window. Returns whether it
+/// ended in the recorded state.
+pub fn verify(replay: Replay, world: &mut World) -> Result<bool, String> {
+    let mut playback = Playback::new(replay);
+    playback.rewind(world)?;
+    let step_dt = playback.replay.step_dt;
+    while let Some(input) = playback.next_input() {
+        world.step(input, step_dt);
+    }
+    Ok(playback.matches(world))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::STEP_DT;
+    use crate::level::Level;
+
+    /// A flat level with a bonus on the way and an enemy further on.
+    fn world() -> World {
+        let level: Level = serde_json::from_str(
+            r#"{
+                "platforms": [[0, 400, 2000, 40]],
+                "enemies": [
+                    { "pos": [700, 355], "vel": [0, 0], "left_bound": 700, "right_bound": 700 }
+                ],
+                "bonuses": [{ "pos": [300, 370] }],
+                "start": [100, 350],
+                "goal_x": 1900
+            }"#,
+        )
+        .unwrap();
+        let mut world = World::new(vec![level], 1);
+        world.start_run(0);
+        world
+    }
+
+    /// Two seconds walking right, jumping once past the bonus, then shooting
+    /// every half second for two more.
+    fn script() -> Vec<InputFrame> {
+        let mut inputs = Vec::new();
+        for step in 0..480 {
+            let mut input = InputFrame::default();
+            if step < 240 {
+                input.move_right = true;
+                input.jump = step == 180;
+            } else {
+                input.shoot = step % 60 == 0;
+            }
+            inputs.push(input);
+        }
+        inputs
+    }
+
+    #[test]
+    fn replay_round_trips() {
+        let mut world = world();
+        let mut recording = Replay::start(&world, STEP_DT);
+        for input in script() {
+            world.step(input, STEP_DT);
+            recording.record(input, &world);
+        }
+        assert_eq!(recording.final_hash, world.state_hash());
+
+        let replay = Replay::decode(&recording.encode()).unwrap();
+        assert_eq!(replay.inputs, recording.inputs);
+        assert_eq!(replay.final_hash, recording.final_hash);
+        assert!(verify(replay, &mut self::world()).unwrap());
+    }
+
+    #[test]
+    fn broken_replays_are_rejected() {
+        let bytes = Replay::start(&world(), STEP_DT).encode();
+        // Offsets in the header, see the top of the file
+        let patched = |at: usize, value: [u8; 4]| {
+            let mut bytes = bytes.clone();
+            bytes[at..at + 4].copy_from_slice(&value);
+            Replay::decode(&bytes)
+        };
+        assert!(patched(18, 0f32.to_le_bytes()).is_err());
+        assert!(patched(18, f32::NAN.to_le_bytes()).is_err());
+        assert!(patched(18, (-STEP_DT).to_le_bytes()).is_err());
+        assert!(patched(22, 0i32.to_le_bytes()).is_err());
+
+        // Two runs of four billion steps each, which mustn't be expanded
+        let mut huge = bytes[..30].to_vec();
+        huge.extend_from_slice(&2u32.to_le_bytes());
+        for _ in 0..2 {
+            huge.extend_from_slice(&0u16.to_le_bytes());
+            huge.extend_from_slice(&u32::MAX.to_le_bytes());
+        }
+        huge.extend_from_slice(&0u64.to_le_bytes());
+        assert!(Replay::decode(&huge).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+/*
+ The save file: how far the player has got, so a later session can carry
+ on from there. It's written as JSON to the user's data directory
+ (~/.local/share/adventure/save.json on Linux) every time a level is
+ finished.
+*/
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::MAX_HEALTH;
+use crate::data::{self, DataError};
+use crate::world::{Difficulty, World};
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SaveGame {
+    /// The furthest level reached; Continue starts there.
+    pub unlocked_level: usize,
+    /// Score and health on arriving at `unlocked_level`.
+    pub score: i32,
+    pub health: i32,
+    /// For each level, which of its bonuses (by index in the level file)
+    /// have been collected on a run that finished it.
+    #[serde(default)]
+    pub collected_bonuses: Vec<Vec<usize>>,
+    /// The difficulty last played on.
+    #[serde(default)]
+    pub difficulty: Difficulty,
+}
+
+impl SaveGame {
+    /// Where the save file lives, if the platform has a data directory.
+    pub fn path() -> Option<PathBuf> {
+        data::user_data_file("save.json")
+    }
+
+    /// The save file, or `None` if there isn't one yet.
+    pub fn load(file: &Path) -> Result<Option<Self>, DataError> {
+        if !file.exists() {
+            return Ok(None);
+        }
+        data::load_json(file).map(Some)
+    }
+
+    pub fn save(&self, file: &Path) -> Result<(), DataError> {
+        data::save_json(file, self)
+    }
+
+    /// Folds in the progress `world` has just made by entering a new level.
+    /// A run that's behind the saved one only adds its bonuses.
+    pub fn update(&mut self, world: &World) {
+        self.difficulty = world.difficulty;
+        if world.current_level >= self.unlocked_level {
+            self.unlocked_level = world.current_level;
+            self.score = world.player.score;
+            self.health = world.player.health;
+        }
+        if self.collected_bonuses.len() < world.collected_bonuses.len() {
+            self.collected_bonuses
+                .resize(world.collected_bonuses.len(), Vec::new());
+        }
+        for (saved, collected) in self
+            .collected_bonuses
+            .iter_mut()
+            .zip(&world.collected_bonuses)
+        {
+            for &index in collected {
+                if !saved.contains(&index) {
+                    saved.push(index);
+                }
+            }
+            saved.sort_unstable();
+        }
+    }
+
+    /// Puts `world` at the start of the saved level with the saved score
+    /// and health.
+    pub fn restore(&self, world: &mut World) -> Result<(), String> {
+        if self.unlocked_level >= world.levels.len() {
+            return Err(format!(
+                "the save is for level {} but there are only {} levels",
+                self.unlocked_level + 1,
+                world.levels.len()
+            ));
+        }
+        if !(1..=MAX_HEALTH).contains(&self.health) {
+            return Err(format!("health {} is out of range", self.health));
+        }
+        world.start_run(self.unlocked_level);
+        world.carry_over(self.score, self.health);
+        world.collected_bonuses = self.collected_bonuses.clone();
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+/*
+ High scores, kept in scores.json next to the save file: the best
+ TABLE_SIZE final scores with the names of who set them, and for each level
+ the most points scored on it and the fastest time it was finished in.
+*/
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::{self, DataError};
+use crate::world::LevelResult;
+
+pub const TABLE_SIZE: usize = 10;
+pub const MAX_NAME_LEN: usize = 12;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub name: String,
+    pub score: i32,
+}
+
+/// The records for one level. The best score and best time can come from
+/// different runs.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelBest {
+    pub score: i32,
+    pub time: f32,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HighScores {
+    /// Best first.
+    #[serde(default)]
+    pub table: Vec<Entry>,
+    /// By level index; `None` for levels nobody has finished.
+    #[serde(default)]
+    pub levels: Vec<Option<LevelBest>>,
+}
+
+impl HighScores {
+    /// Where the scores live, if the platform has a data directory.
+    pub fn path() -> Option<PathBuf> {
+        data::user_data_file("scores.json")
+    }
+
+    /// The scores in `file`, or an empty table if there's no file yet.
+    pub fn load(file: &Path) -> Result<Self, DataError> {
+        if !file.exists() {
+            return Ok(HighScores::default());
+        }
+        data::load_json(file)
+    }
+
+    pub fn save(&self, file: &Path) -> Result<(), DataError> {
+        data::save_json(file, self)
+    }
+
+    /// Whether `score` would make it into the table.
+    pub fn qualifies(&self, score: i32) -> bool {
+        score > 0 && (self.table.len() < TABLE_SIZE || self.table.iter().any(|e| score > e.score))
+    }
+
+    /// Adds a score, keeping the table sorted and at most TABLE_SIZE long.
+    /// Ties go below the scores already there.
+    pub fn insert(&mut self, name: &str, score: i32) {
+        let at = self.table.partition_point(|e| e.score >= score);
+        let name = name.to_string();
+        self.table.insert(at, Entry { name, score });
+        self.table.truncate(TABLE_SIZE);
+    }
+
+    /// Records a finished level.
+    pub fn record_level(&mut self, result: LevelResult) {
+        if self.levels.len() <= result.level {
+            self.levels.resize(result.level + 1, None);
+        }
+        let best = self.levels[result.level].get_or_insert(LevelBest {
+            score: result.score,
+            time: result.time,
+        });
+        best.score = best.score.max(result.score);
+        best.time = best.time.min(result.time);
+    }
+}
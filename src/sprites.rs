@@ -0,0 +1,170 @@
+/*
+ Sprites cut out of one texture atlas. assets/sprites.json names the image
+ and where each frame is in it:
+
+ {
+     "image": "sprites.png",
+     "frames": {
+         "player": [0, 0, 37, 50],
+         "enemy": [37, 0, 28, 45]
+     }
+ }
+
+ Frames are [x, y, w, h] in pixels and get stretched over whatever they're
+ drawn on. The image path is relative to the metadata file. An optional
+ "animations" entry animates characters with those frames (see
+ animation.rs). Anything missing (the files, or a frame) is drawn with
+ primitives instead, so the game runs without any art at all.
+*/
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+use crate::animation::AnimationSet;
+use crate::data::{self, DataError};
+
+pub const SPRITES_FILE: &str = "assets/sprites.json";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpritesFile {
+    image: String,
+    frames: HashMap<String, [f32; 4]>,
+    #[serde(default)]
+    animations: HashMap<String, AnimationSet>,
+}
+
+impl SpritesFile {
+    /// The frames, checked against an image of `size`. Also checks that the
+    /// animations only use frames there are.
+    fn frames(&self, file: &Path, size: Vec2) -> Result<HashMap<String, Rect>, DataError> {
+        let mut frames = HashMap::new();
+        for (name, &[x, y, w, h]) in &self.frames {
+            if w <= 0.0 || h <= 0.0 || x < 0.0 || y < 0.0 {
+                return Err(DataError::field(
+                    file,
+                    format!("frames.{}", name),
+                    "must have a positive size and position",
+                ));
+            }
+            if x + w > size.x || y + h > size.y {
+                return Err(DataError::field(
+                    file,
+                    format!("frames.{}", name),
+                    format!("goes past the edge of the {}x{} image", size.x, size.y),
+                ));
+            }
+            frames.insert(name.clone(), Rect::new(x, y, w, h));
+        }
+        for (set_name, set) in &self.animations {
+            for (state, animation) in &set.states {
+                let state = format!("{:?}", state).to_lowercase();
+                let field = format!("animations.{}.states.{}", set_name, state);
+                if animation.frames.is_empty() {
+                    return Err(DataError::field(file, field, "needs at least one frame"));
+                }
+                if animation.fps.is_nan() || animation.fps <= 0.0 {
+                    return Err(DataError::field(
+                        file,
+                        field + ".fps",
+                        "must be more than 0",
+                    ));
+                }
+                for (i, frame) in animation.frames.iter().enumerate() {
+                    if !frames.contains_key(frame) {
+                        return Err(DataError::field(
+                            file,
+                            format!("{}.frames[{}]", field, i),
+                            format!("no frame called {}", frame),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(frames)
+    }
+}
+
+#[derive(Default)]
+pub struct Sprites {
+    texture: Option<Texture2D>,
+    frames: HashMap<String, Rect>,
+    animations: HashMap<String, AnimationSet>,
+}
+
+impl Sprites {
+    /// The atlas described by `file`. Without the file there are simply no
+    /// sprites.
+    pub async fn load(file: &Path) -> Result<Self, DataError> {
+        if !file.exists() {
+            return Ok(Sprites::default());
+        }
+        let config: SpritesFile = data::load_json(file)?;
+        let image = file.parent().unwrap_or(Path::new("")).join(&config.image);
+        let texture = load_texture(&image.to_string_lossy())
+            .await
+            .map_err(|e| DataError::field(file, "image", e.to_string()))?;
+        texture.set_filter(FilterMode::Nearest);
+
+        let frames = config.frames(file, texture.size())?;
+        Ok(Sprites {
+            texture: Some(texture),
+            frames,
+            animations: config.animations,
+        })
+    }
+
+    /// The animations for characters drawn as `name`, if there are any.
+    pub fn animations(&self, name: &str) -> Option<&AnimationSet> {
+        self.animations.get(name)
+    }
+
+    /// Draws frame `name` stretched over `dest`, mirrored if `flip_x`, and
+    /// tinted with `color` (WHITE for none). Returns false, drawing nothing,
+    /// if there's no such frame.
+    pub fn draw(&self, name: &str, dest: Rect, flip_x: bool, color: Color) -> bool {
+        let (Some(texture), Some(&source)) = (&self.texture, self.frames.get(name)) else {
+            return false;
+        };
+        let params = DrawTextureParams {
+            dest_size: Some(dest.size()),
+            source: Some(source),
+            flip_x,
+            ..Default::default()
+        };
+        draw_texture_ex(texture, dest.x, dest.y, color, params);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_FILE: &str = "assets/sprites.example.json";
+
+    #[test]
+    fn example_atlas_loads() {
+        let file = Path::new(EXAMPLE_FILE);
+        let config: SpritesFile = data::load_json(file).unwrap();
+        let frames = config.frames(file, vec2(256.0, 80.0)).unwrap();
+        assert_eq!(frames["player_walk_1"], Rect::new(111.0, 0.0, 37.0, 50.0));
+        assert!(config.animations.contains_key("player"));
+
+        // Too small an image for the frames
+        let err = config.frames(file, vec2(200.0, 80.0)).unwrap_err();
+        assert!(err.to_string().contains("goes past the edge"));
+    }
+
+    #[test]
+    fn animations_need_known_frames() {
+        let file = Path::new(EXAMPLE_FILE);
+        let mut config: SpritesFile = data::load_json(file).unwrap();
+        config.frames.remove("player_jump");
+        let err = config.frames(file, vec2(256.0, 80.0)).unwrap_err();
+        assert!(err.to_string().contains("no frame called player_jump"));
+    }
+}
@@ -0,0 +1,807 @@
+/*
+ Levels made in the Tiled map editor (https://www.mapeditor.org), in either
+ of its formats: .tmx (XML) or .tmj (JSON). Both are read into the same
+ types below and then turned into a Level:
+
+ - Tile layers become the level's tile grid (see tilemap.rs). A tile's kind
+   is its class in the tileset (solid, one_way, hazard or decoration), else
+   the layer's "kind" property, else solid.
+ - Objects become whatever their class says: start, goal, enemy, bonus,
+   powerup, platform, hazard or checkpoint, configured with custom
+   properties.
+
+ Hidden layers are left out. Tile layers have to use the CSV format (the
+ default), and the map has to be finite with square tiles.
+*/
+
+use std::fs;
+use std::path::Path;
+
+use macroquad::math::{Rect, Vec2, vec2};
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+use serde_json::Value;
+
+use crate::archetype::Archetypes;
+use crate::camera::CameraConfig;
+use crate::data::{self, DataError};
+use crate::entities::{Bonus, Checkpoint, Enemy, PowerUp, PowerUpType};
+use crate::level::{Damage, Hazard, HazardKind, Level, Platform};
+use crate::tilemap::{Tile, TileMap};
+use crate::{BONUS_SIZE, PLAYER_HEIGHT};
+use crate::{PLAYER_WIDTH, POWERUP_SIZE};
+
+/// The high bits of a tile's global id say how it's flipped, which we ignore.
+const GID_MASK: u32 = 0x0fff_ffff;
+
+#[derive(Deserialize)]
+struct Map {
+    width: usize,
+    height: usize,
+    #[serde(rename = "tilewidth")]
+    tile_width: f32,
+    #[serde(rename = "tileheight")]
+    tile_height: f32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    tilesets: Vec<Tileset>,
+    #[serde(default)]
+    layers: Vec<Layer>,
+}
+
+#[derive(Deserialize)]
+struct Tileset {
+    #[serde(default, rename = "firstgid")]
+    first_gid: u32,
+    /// Set for a tileset kept in a file of its own.
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TileInfo>,
+}
+
+/// A tile in a tileset with something set on it.
+#[derive(Deserialize)]
+struct TileInfo {
+    id: u32,
+    #[serde(default, rename = "type", alias = "class")]
+    class: String,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Deserialize)]
+struct Layer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "default_true")]
+    visible: bool,
+    /// Tile layers: global tile ids, row by row.
+    #[serde(default)]
+    data: Option<LayerData>,
+    #[serde(default)]
+    objects: Vec<Object>,
+    /// Group layers: the layers inside.
+    #[serde(default)]
+    layers: Vec<Layer>,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LayerData {
+    Gids(Vec<u32>),
+    /// Base64, possibly compressed; not supported.
+    Encoded(IgnoredAny),
+}
+
+#[derive(Deserialize)]
+struct Object {
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type", alias = "class")]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    point: bool,
+    /// Set for tile objects, which Tiled anchors at their bottom left.
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default = "default_true")]
+    visible: bool,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Deserialize)]
+struct Property {
+    name: String,
+    value: Value,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Whether `file` looks like a Tiled map.
+pub fn is_map(file: &Path) -> bool {
+    file.extension()
+        .is_some_and(|ext| ext == "tmx" || ext == "tmj")
+}
+
+/// Loads the Tiled map in `file` as a level.
+pub fn load(file: &Path, archetypes: &Archetypes) -> Result<Level, DataError> {
+    let mut map = if file.extension().is_some_and(|ext| ext == "tmx") {
+        let text = fs::read_to_string(file).map_err(|e| DataError::new(file, e.to_string()))?;
+        xml::parse_map(file, &text)?
+    } else {
+        data::load_json::<Map>(file)?
+    };
+
+    let dir = file.parent().unwrap_or(Path::new(""));
+    for tileset in &mut map.tilesets {
+        if let Some(source) = &tileset.source {
+            let source = dir.join(source);
+            let external = if source.extension().is_some_and(|ext| ext == "tsx") {
+                let text = fs::read_to_string(&source)
+                    .map_err(|e| DataError::new(&source, e.to_string()))?;
+                xml::parse_tileset(&source, &text)?
+            } else {
+                data::load_json::<Tileset>(&source)?
+            };
+            tileset.tiles = external.tiles;
+        }
+    }
+    convert(file, &map, archetypes)
+}
+
+fn convert(file: &Path, map: &Map, archetypes: &Archetypes) -> Result<Level, DataError> {
+    if map.infinite {
+        return Err(DataError::new(file, "infinite maps aren't supported"));
+    }
+    if map.width == 0 || map.height == 0 {
+        return Err(DataError::new(
+            file,
+            "the map must be at least one tile in size",
+        ));
+    }
+    if map.tile_width.is_nan() || map.tile_width <= 0.0 || map.tile_height <= 0.0 {
+        return Err(DataError::new(file, "tiles must have a positive size"));
+    }
+    if map.tile_width != map.tile_height {
+        return Err(DataError::new(file, "tiles must be square"));
+    }
+
+    let mut level = Level {
+        platforms: Vec::new(),
+        tiles: None,
+        moving_platforms: Vec::new(),
+        hazards: Vec::new(),
+        enemies: Vec::new(),
+        bonuses: Vec::new(),
+        powerups: Vec::new(),
+        checkpoints: Vec::new(),
+        start: Vec2::ZERO,
+        goal_x: 0.0,
+        kill_y: None,
+        camera: CameraConfig::default(),
+    };
+    let mut tiles = TileMap::new(Vec2::ZERO, map.tile_width, map.width, map.height);
+    let mut start = None;
+    let mut goal_x = None;
+
+    let mut layers: Vec<&Layer> = map.layers.iter().rev().collect();
+    while let Some(layer) = layers.pop() {
+        if !layer.visible {
+            continue;
+        }
+        let field = format!("layer {:?}", layer.name);
+        match layer.kind.as_str() {
+            "group" => layers.extend(layer.layers.iter().rev()),
+            "tilelayer" => {
+                let gids = match &layer.data {
+                    Some(LayerData::Gids(gids)) => gids,
+                    Some(LayerData::Encoded(_)) => {
+                        return Err(DataError::field(
+                            file,
+                            field,
+                            "tile layer format must be CSV (Map > Map Properties)",
+                        ));
+                    }
+                    None => continue,
+                };
+                if gids.len() != map.width * map.height {
+                    return Err(DataError::field(
+                        file,
+                        field,
+                        format!(
+                            "has {} tiles, expected {}x{}",
+                            gids.len(),
+                            map.width,
+                            map.height
+                        ),
+                    ));
+                }
+                let layer_kind = property(&layer.properties, "kind").and_then(Value::as_str);
+                for (i, &gid) in gids.iter().enumerate() {
+                    let gid = gid & GID_MASK;
+                    if gid == 0 {
+                        continue;
+                    }
+                    let kind = tile_class(&map.tilesets, gid)
+                        .or(layer_kind)
+                        .unwrap_or("solid");
+                    let tile = tile_kind(kind).ok_or_else(|| {
+                        DataError::field(
+                            file,
+                            &field,
+                            format!(
+                                "unknown tile kind {:?}, expected solid, one_way, hazard or decoration",
+                                kind
+                            ),
+                        )
+                    })?;
+                    tiles.set(i % map.width, i / map.width, tile);
+                }
+            }
+            "objectgroup" => {
+                for object in layer.objects.iter().filter(|o| o.visible) {
+                    let mut field = format!("{}: object {}", field, object.id);
+                    if !object.name.is_empty() {
+                        field += &format!(" {:?}", object.name);
+                    }
+                    match object.class.as_str() {
+                        "start" => start = Some(place(object, vec2(PLAYER_WIDTH, PLAYER_HEIGHT))),
+                        "goal" => goal_x = Some(object.x),
+                        "enemy" => level.enemies.push(enemy(file, &field, object, archetypes)?),
+                        "bonus" => level.bonuses.push(Bonus {
+                            pos: place(object, vec2(BONUS_SIZE, BONUS_SIZE)),
+                            collected: false,
+                        }),
+                        "powerup" => level.powerups.push(powerup(file, &field, object)?),
+                        "platform" => level.platforms.push(Platform {
+                            rect: Rect::new(object.x, object.y, object.width, object.height),
+                            one_way: bool_property(file, &field, object, "one_way")?
+                                .unwrap_or(false),
+                        }),
+                        "hazard" => level.hazards.push(hazard(file, &field, object)?),
+                        "checkpoint" => level.checkpoints.push(Checkpoint {
+                            pos: place(object, vec2(PLAYER_WIDTH, PLAYER_HEIGHT)),
+                            restore: bool_property(file, &field, object, "restore")?
+                                .unwrap_or(false),
+                            reached: false,
+                        }),
+                        class => {
+                            return Err(DataError::field(
+                                file,
+                                field,
+                                format!(
+                                    "unknown class {:?}, expected start, goal, enemy, bonus, powerup, platform, hazard or checkpoint",
+                                    class
+                                ),
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    level.start = start.ok_or_else(|| DataError::new(file, "no object with class \"start\""))?;
+    level.goal_x = goal_x.ok_or_else(|| DataError::new(file, "no object with class \"goal\""))?;
+    if tiles.tiles_in(tiles.bounds()).next().is_some() {
+        level.tiles = Some(tiles);
+    }
+    Ok(level)
+}
+
+/// The class given to tile `gid` in its tileset, or its "kind" property.
+fn tile_class(tilesets: &[Tileset], gid: u32) -> Option<&str> {
+    let tileset = tilesets
+        .iter()
+        .filter(|t| t.first_gid <= gid)
+        .max_by_key(|t| t.first_gid)?;
+    let info = tileset
+        .tiles
+        .iter()
+        .find(|t| t.id == gid - tileset.first_gid)?;
+    if info.class.is_empty() {
+        property(&info.properties, "kind").and_then(Value::as_str)
+    } else {
+        Some(&info.class)
+    }
+}
+
+fn tile_kind(name: &str) -> Option<Tile> {
+    match name {
+        "solid" => Some(Tile::Solid),
+        "one_way" => Some(Tile::OneWay),
+        "hazard" => Some(Tile::Hazard),
+        "decoration" => Some(Tile::Decoration),
+        _ => None,
+    }
+}
+
+/// Top left of a `size` thing placed by `object`: a point is where the
+/// middle of its feet go, anything else is stood on its bottom edge.
+fn place(object: &Object, size: Vec2) -> Vec2 {
+    if object.point {
+        return vec2(object.x - size.x / 2.0, object.y - size.y);
+    }
+    let bottom = if object.gid.is_some() {
+        object.y
+    } else {
+        object.y + object.height
+    };
+    vec2(object.x, bottom - size.y)
+}
+
+/// An enemy of the archetype its "kind" property names (walker by
+/// default). It patrols across its object's width unless it has "left_bound"
+/// and "right_bound" properties, setting off right unless "start_left" is
+/// set.
+fn enemy(
+    file: &Path,
+    field: &str,
+    object: &Object,
+    archetypes: &Archetypes,
+) -> Result<Enemy, DataError> {
+    let kind = property(&object.properties, "kind")
+        .and_then(Value::as_str)
+        .unwrap_or("walker");
+    let Some(archetype) = archetypes.get(kind) else {
+        return Err(DataError::field(
+            file,
+            field,
+            format!(
+                "unknown kind {:?}, expected one of {}",
+                kind,
+                archetypes.list()
+            ),
+        ));
+    };
+    let size = archetype.size;
+    let pos = place(object, size);
+    let number = |name| number_property(file, field, object, name);
+    // An object wider than the enemy is its patrol
+    let across = object.width > size.x;
+    let left_bound = number("left_bound")?.or(across.then_some(pos.x));
+    let right_bound = number("right_bound")?.or(across.then_some(object.x + object.width - size.x));
+    if let (Some(left), Some(right)) = (left_bound, right_bound)
+        && left > right
+    {
+        return Err(DataError::field(
+            file,
+            field,
+            "right_bound is left of left_bound",
+        ));
+    }
+    let mut enemy = Enemy::new(kind, archetype, pos);
+    enemy.left_bound = left_bound;
+    enemy.right_bound = right_bound;
+    let start_left = bool_property(file, field, object, "start_left")?.unwrap_or(false);
+    enemy.vel.x = if start_left { -1.0 } else { 1.0 };
+    Ok(enemy)
+}
+
+fn powerup(file: &Path, field: &str, object: &Object) -> Result<PowerUp, DataError> {
+    let kind = match property(&object.properties, "kind").and_then(Value::as_str) {
+        Some("Health") => PowerUpType::Health,
+        Some("Speed") => PowerUpType::Speed,
+        Some("Invincibility") => PowerUpType::Invincibility,
+        Some("HighJump") => PowerUpType::HighJump,
+        Some("SpreadShot") => PowerUpType::SpreadShot,
+        Some("RapidFire") => PowerUpType::RapidFire,
+        Some("Laser") => PowerUpType::Laser,
+        _ => {
+            return Err(DataError::field(
+                file,
+                field,
+                "needs a \"kind\" property: Health, Speed, Invincibility, HighJump, SpreadShot, RapidFire or Laser",
+            ));
+        }
+    };
+    Ok(PowerUp {
+        pos: place(object, vec2(POWERUP_SIZE, POWERUP_SIZE)),
+        kind,
+        collected: false,
+    })
+}
+
+/// A hazard covering its object, with a "kind" property (spikes by
+/// default) and an optional "damage" one.
+fn hazard(file: &Path, field: &str, object: &Object) -> Result<Hazard, DataError> {
+    let kind = match property(&object.properties, "kind").and_then(Value::as_str) {
+        None | Some("spikes") => HazardKind::Spikes,
+        Some("lava") => HazardKind::Lava,
+        Some("pit") => HazardKind::Pit,
+        Some(kind) => {
+            return Err(DataError::field(
+                file,
+                format!("{}.kind", field),
+                format!(
+                    "unknown hazard kind {:?}, expected spikes, lava or pit",
+                    kind
+                ),
+            ));
+        }
+    };
+    let damage = property(&object.properties, "damage")
+        .map(|value| {
+            Damage::deserialize(value)
+                .map_err(|e| DataError::field(file, format!("{}.damage", field), e.to_string()))
+        })
+        .transpose()?;
+    Ok(Hazard {
+        rect: Rect::new(object.x, object.y, object.width, object.height),
+        kind,
+        damage,
+    })
+}
+
+fn property<'a>(properties: &'a [Property], name: &str) -> Option<&'a Value> {
+    properties.iter().find(|p| p.name == name).map(|p| &p.value)
+}
+
+fn number_property(
+    file: &Path,
+    field: &str,
+    object: &Object,
+    name: &str,
+) -> Result<Option<f32>, DataError> {
+    property(&object.properties, name)
+        .map(|value| {
+            value.as_f64().map(|v| v as f32).ok_or_else(|| {
+                DataError::field(file, format!("{}.{}", field, name), "must be a number")
+            })
+        })
+        .transpose()
+}
+
+fn bool_property(
+    file: &Path,
+    field: &str,
+    object: &Object,
+    name: &str,
+) -> Result<Option<bool>, DataError> {
+    property(&object.properties, name)
+        .map(|value| {
+            value.as_bool().ok_or_else(|| {
+                DataError::field(file, format!("{}.{}", field, name), "must be true or false")
+            })
+        })
+        .transpose()
+}
+
+/// Reading .tmx maps and .tsx tilesets into the same types the JSON
+/// formats deserialize to.
+mod xml {
+    use std::path::Path;
+
+    use roxmltree::{Document, Node};
+    use serde::de::IgnoredAny;
+    use serde_json::Value;
+
+    use super::{Layer, LayerData, Map, Object, Property, TileInfo, Tileset};
+    use crate::data::DataError;
+
+    pub fn parse_map(file: &Path, text: &str) -> Result<Map, DataError> {
+        let doc = parse(file, text)?;
+        let root = doc.root_element();
+        let at = Position { file, doc: &doc };
+        if !root.has_tag_name("map") {
+            return Err(at.error(root, "expected a <map>"));
+        }
+        Ok(Map {
+            width: at.attr(root, "width")?,
+            height: at.attr(root, "height")?,
+            tile_width: at.attr(root, "tilewidth")?,
+            tile_height: at.attr(root, "tileheight")?,
+            infinite: root.attribute("infinite") == Some("1"),
+            tilesets: children(root, "tileset")
+                .map(|node| at.tileset(node))
+                .collect::<Result<_, _>>()?,
+            layers: at.layers(root)?,
+        })
+    }
+
+    pub fn parse_tileset(file: &Path, text: &str) -> Result<Tileset, DataError> {
+        let doc = parse(file, text)?;
+        let at = Position { file, doc: &doc };
+        at.tileset(doc.root_element())
+    }
+
+    fn parse<'a>(file: &Path, text: &'a str) -> Result<Document<'a>, DataError> {
+        Document::parse(text).map_err(|e| DataError {
+            line: Some(e.pos().row as usize),
+            column: Some(e.pos().col as usize),
+            ..DataError::new(file, e.to_string())
+        })
+    }
+
+    fn children<'a, 'i>(
+        node: Node<'a, 'i>,
+        name: &'static str,
+    ) -> impl Iterator<Item = Node<'a, 'i>> {
+        node.children().filter(move |n| n.has_tag_name(name))
+    }
+
+    /// Turns nodes into errors that point at them.
+    struct Position<'a> {
+        file: &'a Path,
+        doc: &'a Document<'a>,
+    }
+
+    impl Position<'_> {
+        fn error(&self, node: Node, message: impl Into<String>) -> DataError {
+            let pos = self.doc.text_pos_at(node.range().start);
+            DataError {
+                line: Some(pos.row as usize),
+                column: Some(pos.col as usize),
+                ..DataError::new(self.file, message)
+            }
+        }
+
+        fn attr<T: std::str::FromStr>(&self, node: Node, name: &str) -> Result<T, DataError> {
+            self.optional(node, name)?
+                .ok_or_else(|| self.error(node, format!("missing attribute {}", name)))
+        }
+
+        fn optional<T: std::str::FromStr>(
+            &self,
+            node: Node,
+            name: &str,
+        ) -> Result<Option<T>, DataError> {
+            node.attribute(name)
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| self.error(node, format!("invalid {} {:?}", name, value)))
+                })
+                .transpose()
+        }
+
+        fn tileset(&self, node: Node) -> Result<Tileset, DataError> {
+            let tiles = children(node, "tile")
+                .map(|tile| {
+                    Ok(TileInfo {
+                        id: self.attr(tile, "id")?,
+                        class: class(tile),
+                        properties: self.properties(tile)?,
+                    })
+                })
+                .collect::<Result<_, DataError>>()?;
+            Ok(Tileset {
+                first_gid: self.optional(node, "firstgid")?.unwrap_or(0),
+                source: node.attribute("source").map(str::to_string),
+                tiles,
+            })
+        }
+
+        fn layers(&self, parent: Node) -> Result<Vec<Layer>, DataError> {
+            let mut layers = Vec::new();
+            for node in parent.children().filter(Node::is_element) {
+                let kind = match node.tag_name().name() {
+                    "layer" => "tilelayer",
+                    "objectgroup" => "objectgroup",
+                    "group" => "group",
+                    _ => continue,
+                };
+                let data = match children(node, "data").next() {
+                    Some(data) => Some(self.layer_data(data)?),
+                    None => None,
+                };
+                let objects = children(node, "object")
+                    .map(|object| self.object(object))
+                    .collect::<Result<_, _>>()?;
+                layers.push(Layer {
+                    kind: kind.to_string(),
+                    name: node.attribute("name").unwrap_or_default().to_string(),
+                    visible: node.attribute("visible") != Some("0"),
+                    data,
+                    objects,
+                    layers: self.layers(node)?,
+                    properties: self.properties(node)?,
+                });
+            }
+            Ok(layers)
+        }
+
+        fn layer_data(&self, data: Node) -> Result<LayerData, DataError> {
+            match data.attribute("encoding") {
+                Some("csv") => data
+                    .text()
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|gid| !gid.is_empty())
+                    .map(|gid| {
+                        gid.parse()
+                            .map_err(|_| self.error(data, format!("invalid tile {:?}", gid)))
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(LayerData::Gids),
+                // The old XML format, one <tile> per cell
+                None => children(data, "tile")
+                    .map(|tile| Ok(self.optional(tile, "gid")?.unwrap_or(0)))
+                    .collect::<Result<_, _>>()
+                    .map(LayerData::Gids),
+                Some(_) => Ok(LayerData::Encoded(IgnoredAny)),
+            }
+        }
+
+        fn object(&self, node: Node) -> Result<Object, DataError> {
+            Ok(Object {
+                id: self.attr(node, "id")?,
+                name: node.attribute("name").unwrap_or_default().to_string(),
+                class: class(node),
+                x: self.attr(node, "x")?,
+                y: self.attr(node, "y")?,
+                width: self.optional(node, "width")?.unwrap_or(0.0),
+                height: self.optional(node, "height")?.unwrap_or(0.0),
+                point: children(node, "point").next().is_some(),
+                gid: self.optional(node, "gid")?,
+                visible: node.attribute("visible") != Some("0"),
+                properties: self.properties(node)?,
+            })
+        }
+
+        /// Custom properties, typed like the JSON format has them.
+        fn properties(&self, node: Node) -> Result<Vec<Property>, DataError> {
+            let mut properties = Vec::new();
+            for props in children(node, "properties") {
+                for prop in children(props, "property") {
+                    let name: String = self.attr(prop, "name")?;
+                    let text = prop.attribute("value").or(prop.text()).unwrap_or_default();
+                    let invalid =
+                        || self.error(prop, format!("{}: invalid number {:?}", name, text));
+                    let value = match prop.attribute("type") {
+                        Some("bool") => Value::Bool(text == "true"),
+                        // Kept whole, as in .tmj, so they pass as integers
+                        Some("int") => text
+                            .parse::<i64>()
+                            .map(|n| Value::Number(n.into()))
+                            .map_err(|_| invalid())?,
+                        Some("float") => text
+                            .parse::<f64>()
+                            .ok()
+                            .and_then(serde_json::Number::from_f64)
+                            .map(Value::Number)
+                            .ok_or_else(invalid)?,
+                        _ => Value::String(text.to_string()),
+                    };
+                    properties.push(Property { name, value });
+                }
+            }
+            Ok(properties)
+        }
+    }
+
+    /// Tiled 1.9 saved the class as "class"; before and since, as "type".
+    fn class(node: Node) -> String {
+        node.attribute("type")
+            .or(node.attribute("class"))
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4x3 map: a floor of solid tiles with a one-way tile above it, the
+    /// start, the goal, a walker patrolling the floor and a bonus.
+    const TMJ: &str = r#"{
+        "width": 4, "height": 3, "tilewidth": 32, "tileheight": 32,
+        "tilesets": [{ "firstgid": 1, "tiles": [{ "id": 1, "type": "one_way" }] }],
+        "layers": [
+            { "type": "tilelayer", "name": "ground", "data": [0, 0, 0, 0, 0, 0, 2, 0, 1, 1, 1, 1] },
+            { "type": "objectgroup", "name": "things", "objects": [
+                { "id": 1, "type": "start", "x": 16, "y": 64, "point": true },
+                { "id": 2, "type": "goal", "x": 120, "y": 0 },
+                { "id": 3, "type": "enemy", "x": 0, "y": 32, "width": 128, "height": 32,
+                  "properties": [{ "name": "kind", "type": "string", "value": "walker" }] },
+                { "id": 4, "type": "bonus", "x": 64, "y": 44, "width": 20, "height": 20 }
+            ] }
+        ]
+    }"#;
+
+    /// The same map as a .tmx, with a spike hazard on top.
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="4" height="3" tilewidth="32" tileheight="32" infinite="0">
+ <tileset firstgid="1" name="tiles" tilewidth="32" tileheight="32" tilecount="2" columns="2">
+  <tile id="1" type="one_way"/>
+ </tileset>
+ <layer id="1" name="ground" width="4" height="3">
+  <data encoding="csv">
+0,0,0,0,
+0,0,2,0,
+1,1,1,1
+</data>
+ </layer>
+ <objectgroup id="2" name="things">
+  <object id="1" type="start" x="16" y="64"><point/></object>
+  <object id="2" type="goal" x="120" y="0"/>
+  <object id="3" type="enemy" x="0" y="32" width="128" height="32">
+   <properties><property name="kind" value="walker"/></properties>
+  </object>
+  <object id="4" type="bonus" x="64" y="44" width="20" height="20"/>
+  <object id="5" type="hazard" x="96" y="56" width="32" height="8">
+   <properties><property name="damage" type="int" value="2"/></properties>
+  </object>
+ </objectgroup>
+</map>
+"#;
+
+    /// Writes `text` to a map file called `name` and loads it.
+    fn import(name: &str, text: &str) -> Result<Level, DataError> {
+        let dir = std::env::temp_dir().join(format!("adventure-tiled-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(name);
+        fs::write(&file, text).unwrap();
+        let archetypes = Archetypes::load(Path::new("enemies.json")).unwrap();
+        let level = load(&file, &archetypes);
+        fs::remove_file(&file).unwrap();
+        level
+    }
+
+    fn check(level: &Level) {
+        let tiles = level.tiles.as_ref().unwrap();
+        assert_eq!(tiles.get(0, 2), Tile::Solid);
+        assert_eq!(tiles.get(2, 1), Tile::OneWay);
+        assert_eq!(tiles.get(0, 0), Tile::Empty);
+        assert_eq!(
+            level.start,
+            vec2(16.0 - PLAYER_WIDTH / 2.0, 64.0 - PLAYER_HEIGHT)
+        );
+        assert_eq!(level.goal_x, 120.0);
+        let enemy = &level.enemies[0];
+        assert_eq!(enemy.kind, "walker");
+        assert_eq!(enemy.left_bound, Some(0.0));
+        assert_eq!(enemy.right_bound, Some(128.0 - enemy.archetype.size.x));
+        assert_eq!(level.bonuses[0].pos, vec2(64.0, 64.0 - BONUS_SIZE));
+    }
+
+    #[test]
+    fn imports_tmj() {
+        check(&import("small.tmj", TMJ).unwrap());
+    }
+
+    #[test]
+    fn imports_tmx() {
+        let level = import("small.tmx", TMX).unwrap();
+        check(&level);
+        let hazard = &level.hazards[0];
+        assert_eq!(hazard.kind, HazardKind::Spikes);
+        assert_eq!(hazard.damage, Some(Damage::Health(2)));
+    }
+
+    #[test]
+    fn rejects_empty_maps_and_tiles() {
+        let size = r#""tilewidth": 32, "tileheight": 32"#;
+        for (from, to, message) in [
+            (r#""width": 4"#, r#""width": 0"#, "at least one tile"),
+            (size, r#""tilewidth": 0, "tileheight": 0"#, "positive size"),
+            (
+                size,
+                r#""tilewidth": -32, "tileheight": -32"#,
+                "positive size",
+            ),
+        ] {
+            let err = import("bad.tmj", &TMJ.replace(from, to)).err().unwrap();
+            assert!(err.message.contains(message), "{}", err);
+        }
+    }
+}
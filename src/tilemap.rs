@@ -0,0 +1,187 @@
+/*
+ Tile grids: level geometry drawn as rows of characters instead of
+ rectangles. A level's optional "tiles" entry:
+
+ "tiles": {
+     "origin": [0, 0],
+     "tile_size": 40,
+     "rows": [
+         "..........",
+         "....==....",
+         "##^^####.."
+     ]
+ }
+
+ '#' is solid, '=' one-way, '^' a hazard (hurts the player), '%'
+ decoration (drawn, never collided with) and '.' or ' ' empty. Rows can be
+ of different lengths. The grid lives alongside the level's free-form
+ platforms, and collision only ever looks at the tiles around a box, so
+ large maps cost no more than small ones.
+*/
+
+use macroquad::math::{Rect, Vec2};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Empty,
+    Solid,
+    OneWay,
+    Hazard,
+    Decoration,
+}
+
+impl Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            '.' | ' ' => Some(Tile::Empty),
+            '#' => Some(Tile::Solid),
+            '=' => Some(Tile::OneWay),
+            '^' => Some(Tile::Hazard),
+            '%' => Some(Tile::Decoration),
+            _ => None,
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Tile::Empty => '.',
+            Tile::Solid => '#',
+            Tile::OneWay => '=',
+            Tile::Hazard => '^',
+            Tile::Decoration => '%',
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "TileMapDef", into = "TileMapDef")]
+pub struct TileMap {
+    /// Top left corner of the grid.
+    pub origin: Vec2,
+    pub tile_size: f32,
+    pub width: usize,
+    pub height: usize,
+    /// Row by row, `width * height` of them.
+    tiles: Vec<Tile>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TileMapDef {
+    #[serde(default)]
+    origin: Vec2,
+    tile_size: f32,
+    rows: Vec<String>,
+}
+
+impl TryFrom<TileMapDef> for TileMap {
+    type Error = String;
+
+    fn try_from(def: TileMapDef) -> Result<Self, String> {
+        if def.tile_size.is_nan() || def.tile_size < 1.0 {
+            return Err("tile_size must be at least 1".to_string());
+        }
+        let width = def
+            .rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let height = def.rows.len();
+        let mut tiles = vec![Tile::Empty; width * height];
+        for (y, row) in def.rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                tiles[y * width + x] = Tile::from_char(c).ok_or_else(|| {
+                    format!(
+                        "unknown tile {:?} in row {} column {}, expected one of . # = ^ %",
+                        c, y, x
+                    )
+                })?;
+            }
+        }
+        Ok(TileMap {
+            origin: def.origin,
+            tile_size: def.tile_size,
+            width,
+            height,
+            tiles,
+        })
+    }
+}
+
+impl From<TileMap> for TileMapDef {
+    fn from(map: TileMap) -> Self {
+        let rows = map
+            .tiles
+            .chunks(map.width.max(1))
+            .map(|row| row.iter().map(|tile| tile.to_char()).collect())
+            .collect();
+        TileMapDef {
+            origin: map.origin,
+            tile_size: map.tile_size,
+            rows,
+        }
+    }
+}
+
+impl TileMap {
+    /// An empty `width` by `height` grid.
+    pub fn new(origin: Vec2, tile_size: f32, width: usize, height: usize) -> Self {
+        TileMap {
+            origin,
+            tile_size,
+            width,
+            height,
+            tiles: vec![Tile::Empty; width * height],
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, tile: Tile) {
+        if x < self.width && y < self.height {
+            self.tiles[y * self.width + x] = tile;
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Tile {
+        if x < self.width && y < self.height {
+            self.tiles[y * self.width + x]
+        } else {
+            Tile::Empty
+        }
+    }
+
+    /// The area the whole grid covers.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(
+            self.origin.x,
+            self.origin.y,
+            self.width as f32 * self.tile_size,
+            self.height as f32 * self.tile_size,
+        )
+    }
+
+    /// Every non-empty tile touching `area`, with the rectangle it covers.
+    pub fn tiles_in(&self, area: Rect) -> impl Iterator<Item = (Rect, Tile)> + '_ {
+        let size = self.tile_size;
+        let cell = |v: f32, origin: f32, count: usize| {
+            ((v - origin) / size).floor().clamp(0.0, count as f32) as usize
+        };
+        let x0 = cell(area.left(), self.origin.x, self.width);
+        let x1 = cell(area.right() + size, self.origin.x, self.width);
+        let y0 = cell(area.top(), self.origin.y, self.height);
+        let y1 = cell(area.bottom() + size, self.origin.y, self.height);
+        (y0..y1).flat_map(move |y| {
+            (x0..x1).filter_map(move |x| {
+                let tile = self.get(x, y);
+                let rect = Rect::new(
+                    self.origin.x + x as f32 * size,
+                    self.origin.y + y as f32 * size,
+                    size,
+                    size,
+                );
+                (tile != Tile::Empty).then_some((rect, tile))
+            })
+        })
+    }
+}
@@ -0,0 +1,197 @@
+/*
+ What the player shoots with. Everyone starts with the pistol, which never
+ runs out; the others come from power-ups ("kind": "SpreadShot",
+ "RapidFire" or "Laser") with a magazine and some spare ammo, and are
+ dropped once both are used up. Picking up a weapon the player already has
+ adds to its ammo. Each kind's numbers are in WeaponKind::stats(); the
+ shooting itself is World::shoot.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::BULLET_SPEED;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum WeaponKind {
+    Pistol,
+    SpreadShot,
+    RapidFire,
+    Laser,
+}
+
+/// How a kind of weapon fires.
+pub struct WeaponStats {
+    /// Seconds from one shot to the next.
+    pub interval: f32,
+    /// Whether holding Shoot keeps firing.
+    pub automatic: bool,
+    /// Bullet speed in pixels per second.
+    pub speed: f32,
+    /// Bullets per shot.
+    pub count: u32,
+    /// The angle, in radians, that a shot's bullets are fanned out over. A
+    /// single bullet goes off somewhere within it at random.
+    pub spread: f32,
+    /// Health an enemy loses to a bullet.
+    pub damage: i32,
+    /// Whether bullets go on through the enemies they hit.
+    pub piercing: bool,
+    /// How many times a bullet ricochets off walls.
+    pub bounces: u32,
+    /// Shots between reloads; the pistol never reloads.
+    pub magazine: Option<u32>,
+    /// Seconds a reload takes.
+    pub reload_time: f32,
+    /// Spare shots that come with a pick-up, on top of a full magazine.
+    pub ammo: u32,
+}
+
+impl WeaponKind {
+    pub fn stats(self) -> WeaponStats {
+        match self {
+            WeaponKind::Pistol => WeaponStats {
+                interval: 0.2,
+                automatic: false,
+                speed: BULLET_SPEED,
+                count: 1,
+                spread: 0.0,
+                damage: 1,
+                piercing: false,
+                bounces: 0,
+                magazine: None,
+                reload_time: 0.0,
+                ammo: 0,
+            },
+            WeaponKind::SpreadShot => WeaponStats {
+                interval: 0.5,
+                automatic: false,
+                speed: 450.0,
+                count: 5,
+                spread: 0.6,
+                damage: 1,
+                piercing: false,
+                bounces: 0,
+                magazine: Some(4),
+                reload_time: 1.2,
+                ammo: 16,
+            },
+            WeaponKind::RapidFire => WeaponStats {
+                interval: 0.08,
+                automatic: true,
+                speed: 600.0,
+                count: 1,
+                spread: 0.12,
+                damage: 1,
+                piercing: false,
+                bounces: 0,
+                magazine: Some(30),
+                reload_time: 1.5,
+                ammo: 90,
+            },
+            WeaponKind::Laser => WeaponStats {
+                interval: 0.35,
+                automatic: false,
+                speed: 900.0,
+                count: 1,
+                spread: 0.0,
+                damage: 2,
+                piercing: true,
+                bounces: 2,
+                magazine: Some(6),
+                reload_time: 1.0,
+                ammo: 18,
+            },
+        }
+    }
+
+    /// How it's called in the HUD.
+    pub fn name(self) -> &'static str {
+        match self {
+            WeaponKind::Pistol => "Pistol",
+            WeaponKind::SpreadShot => "Spread shot",
+            WeaponKind::RapidFire => "Rapid fire",
+            WeaponKind::Laser => "Laser",
+        }
+    }
+}
+
+/// One weapon the player carries.
+#[derive(Clone)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    /// Shots left in the magazine.
+    pub loaded: u32,
+    /// Shots left besides those.
+    pub spare: u32,
+    /// Seconds until it can fire again.
+    pub cooldown: f32,
+    /// Seconds until the reload under way is done, 0 when there is none.
+    pub reload_timer: f32,
+}
+
+impl Weapon {
+    /// A freshly picked up weapon: a full magazine and the spare ammo.
+    pub fn new(kind: WeaponKind) -> Self {
+        let stats = kind.stats();
+        Weapon {
+            kind,
+            loaded: stats.magazine.unwrap_or(0),
+            spare: stats.ammo,
+            cooldown: 0.0,
+            reload_timer: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        // Not below zero, or a long wait would bank several shots at once
+        self.cooldown = (self.cooldown - dt).max(0.0);
+        if self.reload_timer > 0.0 {
+            self.reload_timer -= dt;
+            if self.reload_timer <= 0.0 {
+                self.reload_timer = 0.0;
+                let magazine = self.kind.stats().magazine.unwrap_or(0);
+                let taken = (magazine - self.loaded).min(self.spare);
+                self.loaded += taken;
+                self.spare -= taken;
+            }
+        }
+    }
+
+    pub fn can_fire(&self) -> bool {
+        let loaded = self.kind.stats().magazine.is_none() || self.loaded > 0;
+        self.cooldown <= 0.0 && !self.is_reloading() && loaded
+    }
+
+    /// Uses up a shot, reloading after the last one in the magazine.
+    pub fn fire(&mut self) {
+        let stats = self.kind.stats();
+        self.cooldown = stats.interval;
+        if stats.magazine.is_some() {
+            self.loaded = self.loaded.saturating_sub(1);
+            if self.loaded == 0 && self.spare > 0 {
+                self.reload_timer = stats.reload_time;
+            }
+        }
+    }
+
+    /// Starts a reload of a magazine that isn't full, when there's spare
+    /// ammo to fill it with.
+    pub fn reload(&mut self) {
+        let stats = self.kind.stats();
+        let Some(magazine) = stats.magazine else {
+            return;
+        };
+        if !self.is_reloading() && self.loaded < magazine && self.spare > 0 {
+            self.reload_timer = stats.reload_time;
+        }
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reload_timer > 0.0
+    }
+
+    /// Whether it has nothing left to shoot, ever.
+    pub fn is_spent(&self) -> bool {
+        self.kind.stats().magazine.is_some() && self.loaded == 0 && self.spare == 0
+    }
+}
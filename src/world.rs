@@ -0,0 +1,800 @@
+/*
+ The game simulation. A World owns everything that moves and is advanced
+ with step(); it never reads the keyboard or draws anything, so it can run
+ without a window (tests, bots, servers). main.rs turns key presses into an
+ InputFrame and render.rs draws the result.
+*/
+
+use macroquad::math::{Rect, Vec2, vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::entities::{Bonus, Bullet, Checkpoint, Enemy, Player, PowerUp, PowerUpType, Team};
+use crate::level::{Damage, HazardKind, Level};
+use crate::moving_platform::MovingPlatform;
+use crate::physics::{self, Solids};
+use crate::rng::Rng;
+use crate::weapon::WeaponKind;
+use crate::{JUMP_SPEED, KILL_BOUNCE, MAX_HEALTH, PLAYER_HEIGHT, PLAYER_WIDTH};
+
+/// How far past the level's edges bullets fly before they're gone.
+const BULLET_MARGIN: f32 = 400.0;
+/// How much more stomping on an enemy is worth than shooting it.
+const STOMP_BONUS: i32 = 50;
+
+/// Everything the player asked for during one step. `jump`, `shoot`,
+/// `switch_weapon` and `reload` are "pressed this step", the rest are
+/// "held".
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct InputFrame {
+    pub move_left: bool,
+    pub move_right: bool,
+    pub aim_up: bool,
+    pub aim_down: bool,
+    pub run: bool,
+    pub jump: bool,
+    pub shoot: bool,
+    /// Shoot held down, which keeps automatic weapons firing.
+    pub firing: bool,
+    pub switch_weapon: bool,
+    pub reload: bool,
+}
+
+impl InputFrame {
+    /// -1.0 for left, 1.0 for right, 0.0 for neither (or both).
+    pub fn move_dir(&self) -> f32 {
+        let mut dir = 0.0;
+        if self.move_left {
+            dir -= 1.0;
+        }
+        if self.move_right {
+            dir += 1.0;
+        }
+        dir
+    }
+
+    /// The same input with the one-off presses cleared, for the following steps.
+    pub fn held(self) -> Self {
+        InputFrame {
+            jump: false,
+            shoot: false,
+            switch_weapon: false,
+            reload: false,
+            ..self
+        }
+    }
+
+    /// One bit per field, for replay files.
+    pub fn to_bits(self) -> u16 {
+        let fields = [
+            self.move_left,
+            self.move_right,
+            self.aim_up,
+            self.aim_down,
+            self.run,
+            self.jump,
+            self.shoot,
+            self.firing,
+            self.switch_weapon,
+            self.reload,
+        ];
+        fields
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &on)| bits | (on as u16) << i)
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        let bit = |i: u16| bits & (1 << i) != 0;
+        InputFrame {
+            move_left: bit(0),
+            move_right: bit(1),
+            aim_up: bit(2),
+            aim_down: bit(3),
+            run: bit(4),
+            jump: bit(5),
+            shoot: bit(6),
+            firing: bit(7),
+            switch_weapon: bit(8),
+            reload: bit(9),
+        }
+    }
+}
+
+/// How forgiving getting hurt is.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub enum Difficulty {
+    /// Hits knock the player back. Running out of health sends them back
+    /// to the last checkpoint with full health.
+    Easy,
+    /// Hits knock the player back. Running out of health ends the game.
+    #[default]
+    Normal,
+    /// Every hit sends the player back to the last checkpoint. Running out
+    /// of health ends the game.
+    Hard,
+}
+
+impl Difficulty {
+    pub fn next(self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+
+    /// Seconds the player can't be hurt again after a hit.
+    fn invulnerable_time(self) -> f32 {
+        match self {
+            Difficulty::Easy => 2.0,
+            Difficulty::Normal => 1.2,
+            Difficulty::Hard => 1.0,
+        }
+    }
+
+    /// For replay files.
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard]
+            .get(byte as usize)
+            .copied()
+    }
+}
+
+/// How one level went, from entering it to reaching its goal.
+#[derive(Clone, Copy, Debug)]
+pub struct LevelResult {
+    pub level: usize,
+    /// Points scored on the level alone.
+    pub score: i32,
+    /// Seconds spent on it.
+    pub time: f32,
+}
+
+/// The last checkpoint reached on the current level, and for one that
+/// restores, how things were when it was reached.
+struct ActiveCheckpoint {
+    index: usize,
+    saved: Option<SavedProgress>,
+}
+
+struct SavedProgress {
+    enemies: Vec<Enemy>,
+    bonuses: Vec<Bonus>,
+    powerups: Vec<PowerUp>,
+    score: i32,
+}
+
+pub struct World {
+    pub levels: Vec<Level>,
+    pub current_level: usize,
+    pub player: Player,
+    pub moving_platforms: Vec<MovingPlatform>,
+    pub enemies: Vec<Enemy>,
+    pub bonuses: Vec<Bonus>,
+    pub powerups: Vec<PowerUp>,
+    pub checkpoints: Vec<Checkpoint>,
+    pub bullets: Vec<Bullet>,
+    pub won: bool,
+    /// For each level finished this game, the indices of the bonuses
+    /// collected on it.
+    pub collected_bonuses: Vec<Vec<usize>>,
+    /// Seconds since the current level was entered.
+    pub level_time: f32,
+    /// Set whenever a level is finished; taken by whoever keeps records.
+    pub last_completed: Option<LevelResult>,
+    /// Where bullets hit something during the last step, for effects.
+    pub impacts: Vec<Vec2>,
+    /// Seeds anything random in the simulation; each run starts `rng` over
+    /// from it.
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    rng: Rng,
+    level_start_score: i32,
+    checkpoint: Option<ActiveCheckpoint>,
+}
+
+impl World {
+    /// Starts a new game on the first of `levels`, which must not be empty.
+    pub fn new(levels: Vec<Level>, seed: u64) -> Self {
+        let player = Player::new(levels[0].start);
+        let mut world = World {
+            levels,
+            current_level: 0,
+            player,
+            moving_platforms: Vec::new(),
+            enemies: Vec::new(),
+            bonuses: Vec::new(),
+            powerups: Vec::new(),
+            checkpoints: Vec::new(),
+            bullets: Vec::new(),
+            won: false,
+            collected_bonuses: Vec::new(),
+            level_time: 0.0,
+            last_completed: None,
+            impacts: Vec::new(),
+            seed,
+            difficulty: Difficulty::default(),
+            rng: Rng::new(seed),
+            level_start_score: 0,
+            checkpoint: None,
+        };
+        world.start_run(0);
+        world
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.levels[self.current_level]
+    }
+
+    /// Back to the first level with a fresh player.
+    pub fn restart(&mut self) {
+        self.collected_bonuses.clear();
+        self.start_run(0);
+    }
+
+    /// The current level from scratch with a fresh player.
+    pub fn restart_level(&mut self) {
+        self.start_run(self.current_level);
+    }
+
+    /// Puts a fresh player at the start of level `index`, with the level
+    /// and everything else as if the game had just been launched there.
+    pub fn start_run(&mut self, index: usize) {
+        self.won = false;
+        self.rng = Rng::new(self.seed);
+        self.player = Player::new(self.levels[index].start);
+        self.load_level(index);
+    }
+
+    /// Gives the player the score and health of an earlier run that got to
+    /// this level, as if carried over from the one before.
+    pub fn carry_over(&mut self, score: i32, health: i32) {
+        self.player.score = score;
+        self.player.health = health;
+        self.level_start_score = score;
+    }
+
+    /// A fingerprint of everything that moves, to check that a replay ended
+    /// up exactly where the recording did.
+    pub fn state_hash(&self) -> u64 {
+        // FNV-1a
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut add = |value: u32| {
+            for byte in value.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        let player = &self.player;
+        add(self.current_level as u32);
+        add(self.difficulty.to_byte() as u32);
+        add(self.won as u32);
+        for v in [player.pos, player.vel] {
+            add(v.x.to_bits());
+            add(v.y.to_bits());
+        }
+        add(player.score as u32);
+        add(player.health as u32);
+        add(player.alive as u32);
+        add(player.weapon as u32);
+        for weapon in &player.weapons {
+            add(weapon.loaded);
+            add(weapon.spare);
+        }
+        add(self
+            .checkpoint
+            .as_ref()
+            .map_or(u32::MAX, |c| c.index as u32));
+        for moving in &self.moving_platforms {
+            add(moving.offset.x.to_bits());
+            add(moving.offset.y.to_bits());
+        }
+        for enemy in &self.enemies {
+            for v in [enemy.pos, enemy.vel] {
+                add(v.x.to_bits());
+                add(v.y.to_bits());
+            }
+            add(enemy.alive as u32);
+            add(enemy.health as u32);
+            add(enemy.state as u32);
+        }
+        for bullet in &self.bullets {
+            add(bullet.pos.x.to_bits());
+            add(bullet.pos.y.to_bits());
+        }
+        for bonus in &self.bonuses {
+            add(bonus.collected as u32);
+        }
+        for powerup in &self.powerups {
+            add(powerup.collected as u32);
+        }
+        // The next number it would draw stands in for its state
+        let next = self.rng.clone().next_u64();
+        add(next as u32);
+        add((next >> 32) as u32);
+        hash
+    }
+
+    fn load_level(&mut self, index: usize) {
+        self.current_level = index;
+        let level = &self.levels[index];
+        self.moving_platforms = level.moving_platforms.clone();
+        self.enemies = level.enemies.clone();
+        for enemy in &mut self.enemies {
+            enemy.prev_pos = enemy.pos;
+        }
+        self.bonuses = level.bonuses.clone();
+        self.powerups = level.powerups.clone();
+        self.checkpoints = level.checkpoints.clone();
+        self.checkpoint = None;
+        self.bullets.clear();
+        self.level_time = 0.0;
+        self.level_start_score = self.player.score;
+    }
+
+    /// Advances the game by `dt` seconds.
+    pub fn step(&mut self, input: InputFrame, dt: f32) {
+        let playing = self.player.alive && !self.won;
+        self.impacts.clear();
+
+        self.move_platforms(dt, playing);
+
+        if playing {
+            self.level_time += dt;
+            let level = &self.levels[self.current_level];
+            let solids = Solids::new(level, &self.moving_platforms);
+            self.player.update(dt, &input, &solids);
+            self.player_hazards();
+        }
+
+        if playing {
+            if input.switch_weapon {
+                self.player.next_weapon();
+            }
+            if input.reload {
+                self.player.reload();
+            }
+            let weapon = self.player.weapon();
+            let trigger = input.shoot || input.firing && weapon.kind.stats().automatic;
+            if trigger && weapon.can_fire() {
+                self.shoot(&input);
+                self.player.shoot_timer = 0.15;
+            }
+        }
+
+        let level = &self.levels[self.current_level];
+        let solids = Solids::new(level, &self.moving_platforms);
+        let bounds = level.bounds();
+        let area = Rect::new(
+            bounds.x - BULLET_MARGIN,
+            bounds.y - BULLET_MARGIN,
+            bounds.w + 2.0 * BULLET_MARGIN,
+            bounds.h + 2.0 * BULLET_MARGIN,
+        );
+        for bullet in &mut self.bullets {
+            if let Some(at) = bullet.update(dt, &solids, area) {
+                self.impacts.push(at);
+            }
+        }
+        self.bullets.retain(|b| b.alive);
+
+        let solids = Solids::new(&self.levels[self.current_level], &self.moving_platforms);
+        let level = solids.level;
+        let kill_y = level.kill_y();
+        let target = playing.then(|| self.player.rect().center());
+        for enemy in &mut self.enemies {
+            enemy.update(dt, &solids, target);
+            // Enemies only mind falling out and things that kill
+            let killed = enemy.pos.y > kill_y
+                || level
+                    .hazard_at(enemy.rect())
+                    .is_some_and(|hazard| hazard.damage() == Damage::Kill);
+            if killed {
+                enemy.alive = false;
+            }
+        }
+        if playing {
+            let target = self.player.rect().center();
+            for enemy in &mut self.enemies {
+                if let Some(bullet) = enemy.shoot_at(target, dt) {
+                    self.bullets.push(bullet);
+                }
+            }
+        }
+
+        self.bullet_hits();
+        if playing {
+            self.player_shot();
+            self.enemy_contacts();
+        }
+        if self.player.alive && !self.won {
+            self.reach_checkpoints();
+            self.collect_pickups();
+            self.check_goal();
+        }
+    }
+
+    /// Moves the moving platforms along, taking whatever stands on them with
+    /// them and pushing aside whatever is in their way. A crushed player is
+    /// hurt, even while invincible; crushed enemies die.
+    fn move_platforms(&mut self, dt: f32, playing: bool) {
+        for i in 0..self.moving_platforms.len() {
+            self.moving_platforms[i].update(dt);
+            let before = self.moving_platforms[i].prev_rect();
+            if self.moving_platforms[i].rect() == before {
+                continue;
+            }
+
+            let solids = Solids::new(&self.levels[self.current_level], &self.moving_platforms);
+            let player = &mut self.player;
+            let size = vec2(PLAYER_WIDTH, PLAYER_HEIGHT);
+            let crushed = playing
+                && physics::carry(&mut player.pos, player.vel.y, size, before, i, &solids)
+                    .is_none();
+            for enemy in self.enemies.iter_mut().filter(|enemy| enemy.alive) {
+                let size = enemy.archetype.size;
+                match physics::carry(&mut enemy.pos, enemy.vel.y, size, before, i, &solids) {
+                    Some(moved) => {
+                        enemy.home += moved.x;
+                        for bound in [&mut enemy.left_bound, &mut enemy.right_bound]
+                            .into_iter()
+                            .flatten()
+                        {
+                            *bound += moved.x;
+                        }
+                    }
+                    None => enemy.alive = false,
+                }
+            }
+            if crushed {
+                self.hurt_player(1, None);
+            }
+        }
+    }
+
+    /// Fires the weapon in hand where the player is aiming.
+    fn shoot(&mut self, input: &InputFrame) {
+        let player = &self.player;
+        let kind = player.weapon().kind;
+        let stats = kind.stats();
+        let speed = stats.speed;
+        let dir = if player.facing_right { 1.0 } else { -1.0 };
+        let moving = input.move_left || input.move_right;
+        let center = vec2(
+            player.pos.x + PLAYER_WIDTH / 2.0,
+            player.pos.y + PLAYER_HEIGHT / 2.0,
+        );
+
+        let (pos, vel) = if input.aim_up && moving {
+            (center, vec2(dir * speed, -speed))
+        } else if input.aim_up {
+            (vec2(center.x, player.pos.y), vec2(0.0, -speed))
+        } else if input.aim_down && moving {
+            (center + vec2(dir * 18.0, 0.0), vec2(dir * speed, speed))
+        } else if input.aim_down {
+            (
+                vec2(center.x, player.pos.y + PLAYER_HEIGHT),
+                vec2(0.0, speed),
+            )
+        } else {
+            (center + vec2(dir * 18.0, 0.0), vec2(dir * speed, 0.0))
+        };
+        for i in 0..stats.count {
+            let angle = if stats.count > 1 {
+                stats.spread * (i as f32 / (stats.count - 1) as f32 - 0.5)
+            } else if stats.spread > 0.0 {
+                self.rng.range(-stats.spread / 2.0, stats.spread / 2.0)
+            } else {
+                0.0
+            };
+            let vel = Vec2::from_angle(angle).rotate(vel);
+            self.bullets.push(Bullet::new(pos, vel, kind, Team::Player));
+        }
+        self.player.fire_weapon();
+    }
+
+    /// The player's bullets hitting enemies. Enemies that bullets can't hurt
+    /// still stop them.
+    fn bullet_hits(&mut self) {
+        for bullet in &mut self.bullets {
+            if !bullet.alive || bullet.team != Team::Player {
+                continue;
+            }
+            let stats = bullet.weapon.stats();
+            for (i, enemy) in self.enemies.iter_mut().enumerate() {
+                if !enemy.alive || bullet.hits.contains(&i) {
+                    continue;
+                }
+                if bullet.rect().overlaps(&enemy.rect()) {
+                    let vulnerable = enemy.archetype.vulnerable_to.to_bullets();
+                    if vulnerable {
+                        enemy.health -= stats.damage;
+                    }
+                    bullet.hits.push(i);
+                    bullet.alive = stats.piercing && vulnerable;
+                    self.impacts.push(bullet.rect().center());
+                    if enemy.health <= 0 {
+                        enemy.alive = false;
+                        if self.player.alive {
+                            self.player.score += enemy.archetype.score;
+                        }
+                    }
+                    if !bullet.alive {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Enemy bullets hitting the player. They're stopped even while the
+    /// player can't be hurt.
+    fn player_shot(&mut self) {
+        let player_rect = self.player.rect();
+        let Some(bullet) = self.bullets.iter_mut().find(|bullet| {
+            bullet.alive && bullet.team == Team::Enemies && bullet.rect().overlaps(&player_rect)
+        }) else {
+            return;
+        };
+        bullet.alive = false;
+        let at = bullet.rect().center();
+        let damage = bullet.weapon.stats().damage;
+        self.impacts.push(at);
+        if !self.player.is_invulnerable() {
+            self.hurt_player(damage, Some(at));
+        }
+    }
+
+    // Jump-on-enemy logic and player-enemy collision
+    fn enemy_contacts(&mut self) {
+        let player = &mut self.player;
+        let mut jumped_on_any = false;
+        for enemy in &mut self.enemies {
+            if !enemy.alive {
+                continue;
+            }
+            let is_colliding = player.rect().overlaps(&enemy.rect());
+
+            // Jump on enemy from above
+            let player_was_above = player.prev_pos.y + PLAYER_HEIGHT <= enemy.pos.y + 4.0; // fudge factor
+            let stompable = enemy.archetype.vulnerable_to.to_stomp();
+            if stompable && is_colliding && player.vel.y > 0.0 && player_was_above {
+                enemy.alive = false;
+                player.vel.y = -JUMP_SPEED * KILL_BOUNCE; // bounce up
+                player.score += enemy.archetype.score + STOMP_BONUS;
+                jumped_on_any = true;
+            }
+        }
+
+        // If not jumping on any enemy, regular collision (damage)
+        if !jumped_on_any && !player.is_invulnerable() {
+            let player_rect = player.rect();
+            if let Some(enemy) = self
+                .enemies
+                .iter()
+                .find(|enemy| enemy.alive && player_rect.overlaps(&enemy.rect()))
+            {
+                let from = enemy.rect().center();
+                self.hurt_player(1, Some(from));
+            }
+        }
+    }
+
+    /// Hurts the player if they're in a hazard or have fallen out of the
+    /// level. Invulnerability keeps off damage but not death, and falling
+    /// still sends the player back.
+    fn player_hazards(&mut self) {
+        let level = self.level();
+        let fell = self.player.pos.y > level.kill_y();
+        let (kind, damage, from) = match level.hazard_at(self.player.rect()) {
+            _ if fell => (HazardKind::Pit, HazardKind::Pit.damage(), None),
+            Some(hazard) => (hazard.kind, hazard.damage(), Some(hazard.rect.center())),
+            None => return,
+        };
+        // There's no knocking the player out of a pit
+        let from = from.filter(|_| kind != HazardKind::Pit);
+        match damage {
+            Damage::Kill => self.hurt_player(self.player.health, None),
+            Damage::Health(points) if !self.player.is_invulnerable() => {
+                self.hurt_player(points, from)
+            }
+            Damage::Health(_) if kind == HazardKind::Pit => self.respawn(),
+            Damage::Health(_) => {}
+        }
+    }
+
+    /// Takes `points` of health from the player, who is then briefly
+    /// invulnerable. A hit `from` somewhere knocks them away from it, except
+    /// on Hard; other hits, and running out of health, send them back to the
+    /// respawn point.
+    fn hurt_player(&mut self, points: i32, from: Option<Vec2>) {
+        let difficulty = self.difficulty;
+        let player = &mut self.player;
+        player.health -= points;
+        player.hurt_timer = 0.4;
+        player.invulnerable_timer = difficulty.invulnerable_time();
+        if player.health <= 0 {
+            if difficulty == Difficulty::Easy {
+                player.health = MAX_HEALTH;
+            } else {
+                player.alive = false;
+            }
+            self.respawn();
+            return;
+        }
+        match from {
+            Some(from) if difficulty != Difficulty::Hard => player.knock_back(from),
+            _ => self.respawn(),
+        }
+    }
+
+    /// Puts the player back at the last checkpoint (or the start), with
+    /// everything as it was there if the checkpoint restores.
+    fn respawn(&mut self) {
+        let pos = self.respawn_point();
+        self.player.reset(pos);
+        if let Some(saved) = self.checkpoint.as_ref().and_then(|c| c.saved.as_ref()) {
+            self.enemies = saved.enemies.clone();
+            for enemy in &mut self.enemies {
+                enemy.prev_pos = enemy.pos;
+            }
+            self.bonuses = saved.bonuses.clone();
+            self.powerups = saved.powerups.clone();
+            self.player.score = saved.score;
+        }
+    }
+
+    /// Where the player comes back after being hurt.
+    fn respawn_point(&self) -> Vec2 {
+        match &self.checkpoint {
+            Some(checkpoint) => self.checkpoints[checkpoint.index].pos,
+            None => self.level().start,
+        }
+    }
+
+    /// Makes a checkpoint the player touches the active one.
+    fn reach_checkpoints(&mut self) {
+        let player_rect = self.player.rect();
+        let active = self.checkpoint.as_ref().map(|c| c.index);
+        let Some(index) = self
+            .checkpoints
+            .iter()
+            .position(|checkpoint| player_rect.overlaps(&checkpoint.rect()))
+            .filter(|&index| Some(index) != active)
+        else {
+            return;
+        };
+        let checkpoint = &mut self.checkpoints[index];
+        checkpoint.reached = true;
+        let saved = checkpoint.restore.then(|| SavedProgress {
+            enemies: self.enemies.clone(),
+            bonuses: self.bonuses.clone(),
+            powerups: self.powerups.clone(),
+            score: self.player.score,
+        });
+        self.checkpoint = Some(ActiveCheckpoint { index, saved });
+    }
+
+    /// Whether checkpoint `index` is where the player would respawn.
+    pub fn is_active_checkpoint(&self, index: usize) -> bool {
+        self.checkpoint.as_ref().is_some_and(|c| c.index == index)
+    }
+
+    fn collect_pickups(&mut self) {
+        let player = &mut self.player;
+        for bonus in &mut self.bonuses {
+            if !bonus.collected && player.rect().overlaps(&bonus.rect()) {
+                bonus.collected = true;
+                player.score += 50;
+            }
+        }
+
+        for powerup in &mut self.powerups {
+            if !powerup.collected && player.rect().overlaps(&powerup.rect()) {
+                powerup.collected = true;
+                match powerup.kind {
+                    PowerUpType::Health => {
+                        player.health = MAX_HEALTH.min(player.health + 1);
+                    }
+                    PowerUpType::Speed => {
+                        player.speed_timer = 5.0;
+                    }
+                    PowerUpType::Invincibility => {
+                        player.invincible_timer = 5.0;
+                    }
+                    PowerUpType::HighJump => {
+                        // NEW
+                        player.high_jump_timer = 5.0;
+                    }
+                    PowerUpType::SpreadShot => player.pick_up(WeaponKind::SpreadShot),
+                    PowerUpType::RapidFire => player.pick_up(WeaponKind::RapidFire),
+                    PowerUpType::Laser => player.pick_up(WeaponKind::Laser),
+                }
+            }
+        }
+    }
+
+    // Level progression and win logic
+    fn check_goal(&mut self) {
+        if self.player.pos.x <= self.level().goal_x {
+            return;
+        }
+        let level = self.current_level;
+        if self.collected_bonuses.len() <= level {
+            self.collected_bonuses.resize(level + 1, Vec::new());
+        }
+        for (i, bonus) in self.bonuses.iter().enumerate() {
+            if bonus.collected && !self.collected_bonuses[level].contains(&i) {
+                self.collected_bonuses[level].push(i);
+            }
+        }
+        self.last_completed = Some(LevelResult {
+            level,
+            score: self.player.score - self.level_start_score,
+            time: self.level_time,
+        });
+        if self.current_level + 1 >= self.levels.len() {
+            self.won = true;
+            return;
+        }
+        self.load_level(self.current_level + 1);
+        let start = self.level().start;
+        let player = &mut self.player;
+        player.reset(start);
+        player.speed_timer = 0.0;
+        player.invincible_timer = 0.0;
+        player.high_jump_timer = 0.0;
+        player.invulnerable_timer = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 120.0;
+
+    /// A flat level with a bonus on the way and an enemy further on.
+    fn world() -> World {
+        let level: Level = serde_json::from_str(
+            r#"{
+                "platforms": [[0, 400, 2000, 40]],
+                "enemies": [
+                    { "pos": [700, 355], "vel": [0, 0], "left_bound": 700, "right_bound": 700 }
+                ],
+                "bonuses": [{ "pos": [300, 370] }],
+                "start": [100, 350],
+                "goal_x": 1900
+            }"#,
+        )
+        .unwrap();
+        World::new(vec![level], 1)
+    }
+
+    #[test]
+    fn world_steps_headlessly() {
+        let mut world = world();
+        // Two seconds walking right, jumping once past the bonus, then
+        // shooting every half second for two more
+        for step in 0..480 {
+            let mut input = InputFrame::default();
+            if step < 240 {
+                input.move_right = true;
+                input.jump = step == 180;
+            } else {
+                input.shoot = step % 60 == 0;
+            }
+            world.step(input, DT);
+        }
+        let player = &world.player;
+        assert!(player.alive && player.on_ground);
+        assert!(player.pos.x > 300.0 && player.pos.x < 700.0);
+        assert!(world.bonuses[0].collected);
+        assert!(!world.enemies[0].alive);
+        assert_eq!(player.score, 50 + 100);
+    }
+}